@@ -36,7 +36,6 @@ fn decode1(op: u8) -> Result<Inst, Error> {
         0x0c => Inc(Dst::Reg(C)),
         0x0d => Dec(Dst::Reg(C)),
         0x0f => RrcA,
-        0x10 => Stop,
         0x12 => Ld(Dst::reg_ref(DE), Src::Reg(A)),
         0x13 => Incw(DstW::Reg(DE)),
         0x14 => Inc(Dst::Reg(D)),
@@ -126,6 +125,8 @@ fn decode2(op1: u8, op2: u8) -> Result<Inst, Error> {
     let inst = match op1 {
         0x06 => Ld(Dst::Reg(B), src),
         0x0e => Ld(Dst::Reg(C), src),
+        // The second byte of STOP is ignored.
+        0x10 => Stop,
         0x16 => Ld(Dst::Reg(D), src),
         0x18 => Jr(Cnd::None, src),
         0x1e => Ld(Dst::Reg(E), src),
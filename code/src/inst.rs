@@ -54,6 +54,19 @@ pub enum Inst {
     Ei,
 }
 
+impl Inst {
+    /// Return the fixed address this instruction may jump to, given the address of the following
+    /// instruction.
+    pub fn jump_target(&self, next_pc: u16) -> Option<u16> {
+        match self {
+            Self::Jr(_, Src::Imm(offset)) => Some(next_pc.wrapping_add(*offset as i8 as u16)),
+            Self::Jp(_, SrcW::Imm(addr)) | Self::Call(_, SrcW::Imm(addr)) => Some(*addr),
+            Self::Rst(addr) => Some(u16::from(*addr)),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[derive(serde::Serialize, serde::Deserialize)]
 pub enum Reg {
@@ -38,8 +38,8 @@ impl Header {
 
         match self.cartridge_type {
             0x00 => None,
-            0x01 | 0x02 | 0x03 => Mbc1,
-            0x11 | 0x12 | 0x13 => Mbc3,
+            0x01..=0x03 => Mbc1,
+            0x11..=0x13 => Mbc3,
             code => Unsupported(code),
         }
     }
@@ -1,13 +1,10 @@
 use code::{Cnd, DstW, Inst, SrcW};
-use log::trace;
 
 use super::execute::Op;
 use super::Cpu;
 
 impl Cpu<'_> {
     pub(super) fn decode(&mut self, inst: Inst) {
-        trace!("{inst}");
-
         debug_assert!(self.s.cpu.stash.is_empty(), "stash leak");
 
         macro_rules! ops {
@@ -47,7 +44,7 @@ impl Cpu<'_> {
             Inst::JpHL => ops!(JumpHL),
             Inst::Call(c, t) => ops!(ReadW(t), Call(c)),
             Inst::Ret(c) => ops!(Return(c)),
-            Inst::Reti => ops!(Return(Cnd::None), Ei),
+            Inst::Reti => ops!(Return(Cnd::None), EnableIme),
             Inst::Rst(v) => ops!(Reset(v), PushW),
             Inst::Rl(d) => ops!(Read(d.into()), Rl, Write(d)),
             Inst::RlA => ops!(RlA),
@@ -66,7 +63,7 @@ impl Cpu<'_> {
             Inst::Set(b, d) => ops!(Read(d.into()), Set(b), Write(d)),
             Inst::Nop => (),
             Inst::Halt => ops!(Halt),
-            Inst::Stop => todo!("stop"),
+            Inst::Stop => ops!(Stop),
             Inst::Scf => ops!(Scf),
             Inst::Ccf => ops!(Ccf),
             Inst::Di => ops!(Di),
@@ -1,4 +1,3 @@
-use anyhow::Result;
 use code::{Cnd, Dst, DstW, Ref, RegW, Src, SrcW};
 
 use crate::bits::BitsExt;
@@ -59,18 +58,21 @@ pub(super) enum Op {
     Res(u8),
     Set(u8),
     Halt,
+    Stop,
+    Dispatch,
     Scf,
     Ccf,
     Di,
     Ei,
+    EnableIme,
     Wait,
 }
 
 impl Cpu<'_> {
-    pub(super) fn execute(&mut self, op: Op) -> Result<()> {
+    pub(super) fn execute(&mut self, op: Op) {
         use Op::*;
         match op {
-            FetchDecode => self.fetch_decode()?,
+            FetchDecode => self.fetch_decode(),
             Read(src) => self.exec_read(src),
             Write(dst) => self.exec_write(dst),
             ReadW(src) => self.exec_read_wide(src),
@@ -119,15 +121,16 @@ impl Cpu<'_> {
             Bit(bit) => self.exec_bit(bit),
             Res(bit) => self.exec_res(bit),
             Set(bit) => self.exec_set(bit),
-            Halt => self.s.cpu.halt = true,
+            Halt => self.exec_halt(),
+            Stop => self.exec_stop(),
+            Dispatch => self.dispatch_interrupt(),
             Scf => self.exec_scf(),
             Ccf => self.exec_ccf(),
-            Di => self.s.cpu.ime = false,
-            Ei => self.s.cpu.ime = true,
+            Di => self.exec_di(),
+            Ei => self.s.cpu.ime_scheduled = true,
+            EnableIme => self.s.cpu.ime = true,
             Wait => self.must_yield(),
         }
-
-        Ok(())
     }
 
     fn exec_read(&mut self, src: Src) {
@@ -508,7 +511,7 @@ impl Cpu<'_> {
 
     fn exec_rlc(&mut self) {
         let x = self.unstash();
-        let result = (x << 1) | (x >> 7);
+        let result = x.rotate_left(1);
 
         self.s.cpu.registers.flags = Flags {
             z: result == 0,
@@ -521,7 +524,7 @@ impl Cpu<'_> {
 
     fn exec_rlc_a(&mut self) {
         let a = self.s.cpu.registers.a;
-        let result = (a << 1) | (a >> 7);
+        let result = a.rotate_left(1);
 
         self.s.cpu.registers.a = result;
         self.s.cpu.registers.flags = Flags {
@@ -558,7 +561,7 @@ impl Cpu<'_> {
 
     fn exec_rrc(&mut self) {
         let x = self.unstash();
-        let result = (x >> 1) | (x << 7);
+        let result = x.rotate_right(1);
 
         self.s.cpu.registers.flags = Flags {
             z: result == 0,
@@ -571,7 +574,7 @@ impl Cpu<'_> {
 
     fn exec_rrc_a(&mut self) {
         let a = self.s.cpu.registers.a;
-        let result = (a >> 1) | (a << 7);
+        let result = a.rotate_right(1);
 
         self.s.cpu.registers.a = result;
         self.s.cpu.registers.flags = Flags {
@@ -621,7 +624,7 @@ impl Cpu<'_> {
 
     fn exec_swap(&mut self) {
         let x = self.unstash();
-        let result = (x << 4) | (x >> 4);
+        let result = x.rotate_right(4);
 
         self.s.cpu.registers.flags = Flags {
             z: result == 0,
@@ -664,11 +667,58 @@ impl Cpu<'_> {
         self.s.cpu.registers.flags.c = !self.s.cpu.registers.flags.c;
     }
 
+    fn exec_di(&mut self) {
+        // DI also cancels a preceding EI that hasn't taken effect yet.
+        self.s.cpu.ime = false;
+        self.s.cpu.ime_scheduled = false;
+    }
+
+    fn exec_halt(&mut self) {
+        match (self.pending_interrupt(), self.s.cpu.ime) {
+            (None, _) => self.s.cpu.halt = true,
+            // The interrupt is dispatched right away.
+            (Some(_), true) => (),
+            // The HALT bug: HALT ends immediately, and the CPU fails to increment PC after reading
+            // the next opcode.
+            (Some(_), false) => self.s.cpu.halt_bug = true,
+        }
+    }
+
+    /// Reference: https://gbdev.io/pandocs/Reducing_Power_Consumption.html#using-the-stop-instruction
+    fn exec_stop(&mut self) {
+        let pending = self.pending_interrupt().is_some();
+        if pending {
+            // With an interrupt pending, STOP is a 1-byte instruction.
+            self.s.cpu.pc = self.s.cpu.pc.wrapping_sub(1);
+        }
+
+        if self.s.joypad.any_line_low() {
+            // STOP mode would end immediately, so the CPU halts instead.
+            self.s.cpu.halt = !pending;
+            return;
+        }
+
+        if self.speed_switch_requested() {
+            return;
+        }
+
+        self.s.timer.write_div(0);
+        self.s.cpu.stop = true;
+    }
+
+    /// Returns whether a CPU speed switch was prepared through KEY1.
+    ///
+    /// KEY1 only exists on the CGB, so on the DMG STOP always enters STOP mode. CGB support would
+    /// perform the speed switch here.
+    fn speed_switch_requested(&self) -> bool {
+        false
+    }
+
     fn stash(&mut self, value: u8) {
         self.s.cpu.stash.push(value);
     }
 
-    fn stash_wide(&mut self, value: u16) {
+    pub(super) fn stash_wide(&mut self, value: u16) {
         let [a, b] = value.to_le_bytes();
         self.stash(a);
         self.stash(b);
@@ -1,4 +1,4 @@
-use code::{Cnd, SrcW};
+use std::mem;
 
 use crate::bits::BitsExt;
 
@@ -46,23 +46,47 @@ impl Interrupt {
 }
 
 impl Cpu<'_> {
+    /// Reference: https://gbdev.io/pandocs/Interrupts.html#interrupt-handling
     pub(super) fn handle_interrupt(&mut self) {
-        let Some(irpt) = self.pending_interrupt() else { return };
+        if self.pending_interrupt().is_none() {
+            return;
+        }
 
-        self.s.cpu.halt = false;
+        let was_halted = mem::take(&mut self.s.cpu.halt);
+        if !self.s.cpu.ime {
+            return;
+        }
+        self.s.cpu.ime = false;
+        self.s.cpu.ime_scheduled = false;
 
-        if self.s.cpu.ime {
-            self.clear_interrupt(irpt);
-            self.s.cpu.ime = false;
+        // The dispatch takes 5 M-cycles: two wait states, two to push PC, and one to jump to the
+        // interrupt vector.
+        let pc = self.s.cpu.pc;
+        self.stash_wide(pc);
+        self.push_ops([Op::Wait, Op::Push, Op::Dispatch, Op::Push, Op::Wait]);
+        if was_halted {
+            // Exiting HALT takes an additional M-cycle.
+            self.push_op(Op::Wait);
+        }
 
-            let target = SrcW::Imm(irpt.vector());
-            self.push_ops([Op::ReadW(target), Op::Call(Cnd::None)]);
+        self.must_yield();
+    }
 
-            self.must_yield();
-        }
+    /// Select the interrupt to dispatch and jump to its vector.
+    ///
+    /// This happens only after the high byte of PC was pushed. If that push overwrote IE so that
+    /// no interrupt is pending anymore, the dispatch is cancelled and jumps to 0x0000 instead.
+    pub(super) fn dispatch_interrupt(&mut self) {
+        self.s.cpu.pc = match self.pending_interrupt() {
+            Some(irpt) => {
+                self.clear_interrupt(irpt);
+                irpt.vector()
+            }
+            None => 0x0000,
+        };
     }
 
-    fn pending_interrupt(&self) -> Option<Interrupt> {
+    pub(super) fn pending_interrupt(&self) -> Option<Interrupt> {
         let interrupts = &self.s.cpu.interrupts;
         let pending_bits = interrupts.flag_bits & interrupts.enable_bits;
         let pending_bits = pending_bits.bits(0..=4);
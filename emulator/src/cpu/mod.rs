@@ -1,5 +1,9 @@
-use anyhow::{bail, Result};
+use std::mem;
 
+use log::warn;
+
+use crate::debugger::{describe_addr, Access, Debugger, Location, StopReason};
+use crate::error::IllegalOpcodePolicy;
 use crate::mmu::Mmu;
 use crate::state::State;
 
@@ -9,8 +13,11 @@ mod decode;
 mod execute;
 mod interrupt;
 mod state;
+#[cfg(test)]
+mod tests;
 
 pub(crate) use self::interrupt::Interrupt;
+pub use self::state::{CpuSnapshot, Register};
 pub(crate) use self::state::{CpuState, InterruptState};
 
 pub(crate) struct Cpu<'a> {
@@ -26,20 +33,42 @@ impl<'a> Cpu<'a> {
         }
     }
 
-    pub fn step(&mut self) -> Result<()> {
+    pub fn step(&mut self) {
+        if self.s.cpu.locked {
+            return;
+        }
+        if self.s.cpu.stop {
+            // STOP mode ends once a selected joypad line goes low.
+            if !self.s.joypad.any_line_low() {
+                return;
+            }
+            self.s.cpu.stop = false;
+        }
+
         while let Some(op) = self.next_op() {
-            self.execute(op)?;
+            self.execute(op);
         }
         if self.must_yield {
-            return Ok(());
+            return;
         };
 
         self.handle_interrupt();
-        if self.must_yield || self.s.cpu.halt {
-            Ok(())
-        } else {
-            self.fetch_decode()
+        if self.must_yield || self.s.cpu.halt || self.s.cpu.stop {
+            return;
         }
+
+        let mut debugger = Debugger::new(self.s);
+        if debugger.check_instruction() {
+            return;
+        }
+        debugger.begin_instruction();
+
+        // EI takes effect only once the next instruction is fetched, so interrupts are dispatched
+        // after that instruction at the earliest.
+        if mem::take(&mut self.s.cpu.ime_scheduled) {
+            self.s.cpu.ime = true;
+        }
+        self.fetch_decode();
     }
 
     fn must_yield(&mut self) {
@@ -67,7 +96,7 @@ impl<'a> Cpu<'a> {
         self.s.cpu.todo.extend(ops.into_iter().rev());
     }
 
-    fn fetch_decode(&mut self) -> Result<()> {
+    fn fetch_decode(&mut self) {
         let byte = self.read_program();
         let ops = &mut self.s.cpu.stash;
         ops.push(byte);
@@ -80,25 +109,62 @@ impl<'a> Cpu<'a> {
             Err(code::Error::TooFewBytes) => {
                 self.push_op(Op::FetchDecode);
             }
-            Err(error) => bail!("instruction decode error: {error}"),
+            Err(_) => {
+                let opcode = self.s.cpu.stash[0];
+                self.s.cpu.stash.clear();
+                self.illegal_opcode(opcode);
+            }
+        }
+    }
+
+    fn illegal_opcode(&mut self, opcode: u8) {
+        let pc = self.s.debug.inst_pc();
+        match self.s.debug.illegal_opcode_policy() {
+            IllegalOpcodePolicy::Lock => {
+                let addr = describe_addr(self.s, pc);
+                warn!("illegal opcode {opcode:#04x} at {addr}, locking up the CPU");
+                self.s.cpu.locked = true;
+            }
+            IllegalOpcodePolicy::Break => {
+                // Resuming executes the illegal opcode again, so it is reported again.
+                self.s.cpu.pc = pc;
+                let location = Location {
+                    bank: self.s.mmu.bank_at(pc),
+                    addr: pc,
+                };
+                Debugger::new(self.s).stop(StopReason::IllegalOpcode { location, opcode });
+            }
         }
-        Ok(())
     }
 
     fn read_program(&mut self) -> u8 {
         let pc = self.s.cpu.pc;
-        let value = self.read_memory(pc);
-        self.s.cpu.pc += 1;
+        self.must_yield();
+        let value = self.read_bus(pc);
+        if !mem::take(&mut self.s.cpu.halt_bug) {
+            self.s.cpu.pc += 1;
+        }
         value
     }
 
     fn read_memory(&mut self, addr: u16) -> u8 {
         self.must_yield();
-        Mmu::new(self.s).read(addr)
+        Debugger::new(self.s).check_access(addr, Access::Read);
+        self.read_bus(addr)
     }
 
     fn write_memory(&mut self, addr: u16, value: u8) {
         self.must_yield();
-        Mmu::new(self.s).write(addr, value)
+        Debugger::new(self.s).check_access(addr, Access::Write);
+        if !self.s.dma.blocks_write(addr) {
+            Mmu::new(self.s).cpu_write(addr, value);
+        }
+    }
+
+    fn read_bus(&mut self, addr: u16) -> u8 {
+        match self.s.dma.blocked_read(addr) {
+            Some(value) => value,
+            None => Mmu::new(self.s).cpu_read(addr),
+        }
     }
 }
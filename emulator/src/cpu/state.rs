@@ -11,12 +11,146 @@ pub(crate) struct CpuState {
     pub(super) registers: Registers,
     pub(super) pc: u16,
     pub(super) ime: bool,
+    /// Set by EI, to enable IME after the next instruction.
+    #[serde(default)]
+    pub(super) ime_scheduled: bool,
     pub(super) halt: bool,
+    #[serde(default)]
+    pub(super) halt_bug: bool,
+    #[serde(default)]
+    pub(super) stop: bool,
+    /// Set after an illegal opcode, which locks up the CPU for good.
+    #[serde(default)]
+    pub(super) locked: bool,
 
     pub(super) todo: Vec<Op>,
     pub(super) stash: Vec<u8>,
 }
 
+impl CpuState {
+    pub fn read_register(&self, reg: Register) -> u16 {
+        self.snapshot().read(reg)
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stop
+    }
+
+    pub fn snapshot(&self) -> CpuSnapshot {
+        let r = &self.registers;
+        CpuSnapshot {
+            a: r.a,
+            f: r.flags.to_u8(),
+            b: r.b,
+            c: r.c,
+            d: r.d,
+            e: r.e,
+            h: r.h,
+            l: r.l,
+            sp: r.sp,
+            pc: self.pc,
+            ime: self.ime,
+            halted: self.halt,
+            stopped: self.stop,
+        }
+    }
+
+    pub fn restore(&mut self, snap: &CpuSnapshot) {
+        let r = &mut self.registers;
+        r.a = snap.a;
+        r.flags = Flags::from_u8(snap.f);
+        r.b = snap.b;
+        r.c = snap.c;
+        r.d = snap.d;
+        r.e = snap.e;
+        r.h = snap.h;
+        r.l = snap.l;
+        r.sp = snap.sp;
+        self.pc = snap.pc;
+        self.ime = snap.ime;
+        self.halt = snap.halted;
+        self.stop = snap.stopped;
+    }
+}
+
+/// A copy of the CPU's programmer-visible state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CpuSnapshot {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    /// Interrupt master enable flag.
+    pub ime: bool,
+    pub halted: bool,
+    /// Whether the CPU is in STOP mode, waiting for a joypad input.
+    pub stopped: bool,
+}
+
+impl CpuSnapshot {
+    pub fn af(&self) -> u16 {
+        u16::from_be_bytes([self.a, self.f])
+    }
+
+    pub fn bc(&self) -> u16 {
+        u16::from_be_bytes([self.b, self.c])
+    }
+
+    pub fn de(&self) -> u16 {
+        u16::from_be_bytes([self.d, self.e])
+    }
+
+    pub fn hl(&self) -> u16 {
+        u16::from_be_bytes([self.h, self.l])
+    }
+
+    pub fn read(&self, reg: Register) -> u16 {
+        match reg {
+            Register::A => self.a.into(),
+            Register::F => self.f.into(),
+            Register::B => self.b.into(),
+            Register::C => self.c.into(),
+            Register::D => self.d.into(),
+            Register::E => self.e.into(),
+            Register::H => self.h.into(),
+            Register::L => self.l.into(),
+            Register::AF => self.af(),
+            Register::BC => self.bc(),
+            Register::DE => self.de(),
+            Register::HL => self.hl(),
+            Register::SP => self.sp,
+            Register::PC => self.pc,
+        }
+    }
+
+    /// Write a register, truncating `value` to the register's width.
+    pub fn write(&mut self, reg: Register, value: u16) {
+        let [high, low] = value.to_be_bytes();
+        match reg {
+            Register::A => self.a = low,
+            Register::F => self.f = low,
+            Register::B => self.b = low,
+            Register::C => self.c = low,
+            Register::D => self.d = low,
+            Register::E => self.e = low,
+            Register::H => self.h = low,
+            Register::L => self.l = low,
+            Register::AF => [self.a, self.f] = [high, low],
+            Register::BC => [self.b, self.c] = [high, low],
+            Register::DE => [self.d, self.e] = [high, low],
+            Register::HL => [self.h, self.l] = [high, low],
+            Register::SP => self.sp = value,
+            Register::PC => self.pc = value,
+        }
+    }
+}
+
 impl Default for CpuState {
     fn default() -> Self {
         Self {
@@ -24,13 +158,35 @@ impl Default for CpuState {
             registers: Default::default(),
             pc: 0x0100,
             ime: false,
+            ime_scheduled: false,
             halt: false,
+            halt_bug: false,
+            stop: false,
+            locked: false,
             todo: Default::default(),
             stash: Default::default(),
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+
 #[derive(Debug, Default)]
 #[derive(serde::Serialize, serde::Deserialize)]
 pub(crate) struct InterruptState {
@@ -48,7 +204,7 @@ impl InterruptState {
     }
 
     pub fn read_flag(&self) -> u8 {
-        self.flag_bits | 0xe0
+        self.flag_bits
     }
 
     pub fn write_flag(&mut self, value: u8) {
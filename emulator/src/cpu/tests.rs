@@ -0,0 +1,65 @@
+use crate::testing::{emulator, run_to, run_until_stop};
+use crate::{Breakpoint, StopReason};
+
+/// `ld b, 0; ld a, 0x01; ldh (IE), a; ldh (IF), a`, leaving a pending VBlank interrupt.
+const PENDING_VBLANK: [u8; 8] = [0x06, 0x00, 0x3e, 0x01, 0xe0, 0xff, 0xe0, 0x0f];
+
+fn with_pending_vblank(code: &[u8]) -> Vec<u8> {
+    [&PENDING_VBLANK[..], code].concat()
+}
+
+#[test]
+fn ei_takes_effect_after_next_instruction() {
+    // ei; inc b; inc b; jr -2
+    let mut emu = emulator(&with_pending_vblank(&[0xfb, 0x04, 0x04, 0x18, 0xfe]));
+    run_to(&mut emu, 0x0040);
+    assert_eq!(emu.cpu().b, 1);
+}
+
+#[test]
+fn ei_delay_survives_breakpoint() {
+    // ei; inc b; inc b; jr -2
+    let mut emu = emulator(&with_pending_vblank(&[0xfb, 0x04, 0x04, 0x18, 0xfe]));
+    let id = emu.debugger().add_breakpoint(Breakpoint::at(0x0159));
+    let stop = run_until_stop(&mut emu);
+    assert!(matches!(stop, StopReason::Breakpoint(stop_id) if stop_id == id));
+    assert!(!emu.cpu().ime);
+
+    // Resuming still executes the first `inc b` before dispatching the interrupt.
+    emu.debugger().remove_breakpoint(id);
+    run_to(&mut emu, 0x0040);
+    assert_eq!(emu.cpu().b, 1);
+}
+
+#[test]
+fn di_cancels_pending_ei() {
+    // ei; di; inc b; inc b; jr -2
+    let mut emu = emulator(&with_pending_vblank(&[0xfb, 0xf3, 0x04, 0x04, 0x18, 0xfe]));
+    run_to(&mut emu, 0x015c);
+    assert_eq!(emu.cpu().b, 2);
+    assert!(!emu.cpu().ime);
+}
+
+#[test]
+fn halt_bug_repeats_next_byte() {
+    // halt; inc b; jr -2
+    let mut emu = emulator(&with_pending_vblank(&[0x76, 0x04, 0x18, 0xfe]));
+    run_to(&mut emu, 0x015a);
+    assert_eq!(emu.cpu().b, 2);
+    assert!(!emu.cpu().halted);
+}
+
+#[test]
+fn ie_cleared_by_push_cancels_dispatch() {
+    // ld sp, 0x0000; ld a, 0x04; ldh (IE), a; ldh (IF), a; ei; nop; jr -2
+    let code = [
+        0x31, 0x00, 0x00, 0x3e, 0x04, 0xe0, 0xff, 0xe0, 0x0f, 0xfb, 0x00, 0x18, 0xfe,
+    ];
+    let mut emu = emulator(&code);
+    run_to(&mut emu, 0x0000);
+    // The high byte of the return address landed in IE and disabled the timer interrupt, which
+    // therefore stays pending.
+    assert_eq!(emu.peek(0xffff), 0x01);
+    assert_eq!(emu.peek(0xff0f) & 0x04, 0x04);
+    assert!(!emu.cpu().ime);
+}
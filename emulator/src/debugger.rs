@@ -0,0 +1,572 @@
+//! Support for debugging the emulated program.
+//!
+//! The debugger can stop execution at instruction boundaries (breakpoints, execute watchpoints,
+//! stepping) and after memory accesses by the CPU (read/write watchpoints). Stops are reported by
+//! `Emulator::run_until_break`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::mem;
+use std::ops::RangeInclusive;
+
+use code::Inst;
+
+use crate::cpu::{CpuState, Register};
+use crate::error::IllegalOpcodePolicy;
+use crate::frame::Frame;
+use crate::mmu;
+use crate::profiler::Profiler;
+use crate::state::State;
+use crate::symbols::Symbols;
+use crate::trace::Tracer;
+
+pub struct Debugger<'a> {
+    s: &'a mut State,
+}
+
+impl<'a> Debugger<'a> {
+    pub(crate) fn new(state: &'a mut State) -> Self {
+        Self { s: state }
+    }
+
+    /// Add a breakpoint and return its ID.
+    pub fn add_breakpoint(&mut self, bp: Breakpoint) -> u32 {
+        let d = &mut self.s.debug;
+        let id = d.next_id();
+        d.breakpoints.insert(id, bp);
+        id
+    }
+
+    /// Remove the breakpoint with the given ID, returning whether it existed.
+    pub fn remove_breakpoint(&mut self, id: u32) -> bool {
+        self.s.debug.breakpoints.remove(&id).is_some()
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (u32, &Breakpoint)> {
+        self.s.debug.breakpoints.iter().map(|(id, bp)| (*id, bp))
+    }
+
+    /// Add a watchpoint and return its ID.
+    pub fn add_watchpoint(&mut self, wp: Watchpoint) -> u32 {
+        let d = &mut self.s.debug;
+        let id = d.next_id();
+        d.watchpoints.insert(id, wp);
+        id
+    }
+
+    /// Remove the watchpoint with the given ID, returning whether it existed.
+    pub fn remove_watchpoint(&mut self, id: u32) -> bool {
+        self.s.debug.watchpoints.remove(&id).is_some()
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = (u32, &Watchpoint)> {
+        self.s.debug.watchpoints.iter().map(|(id, wp)| (*id, wp))
+    }
+
+    /// Stop at the next instruction.
+    pub fn step_instruction(&mut self) {
+        self.s.debug.step = Some(Step::Instruction);
+    }
+
+    /// Stop at the next instruction, treating calls as a single instruction.
+    pub fn step_over(&mut self) {
+        let pc = self.s.cpu.read_register(Register::PC);
+        let sp = self.s.cpu.read_register(Register::SP);
+
+        let step = match decode_at(self.s, pc) {
+            Some((Inst::Call(..) | Inst::Rst(_), len)) => Step::Over {
+                ret_pc: pc.wrapping_add(len),
+                call_sp: sp,
+            },
+            _ => Step::Instruction,
+        };
+        self.s.debug.step = Some(step);
+    }
+
+    /// Stop after the current function returned.
+    pub fn step_out(&mut self) {
+        let call_sp = self.s.cpu.read_register(Register::SP);
+        self.s.debug.step = Some(Step::Out { call_sp });
+    }
+
+    /// Stop when reaching the instruction at `addr`.
+    pub fn run_to(&mut self, addr: u16) {
+        self.s.debug.step = Some(Step::RunTo(addr));
+    }
+
+    /// Cancel any pending step request, so execution only stops at breakpoints and watchpoints.
+    pub fn resume(&mut self) {
+        self.s.debug.step = None;
+    }
+
+    /// Check whether execution should stop before the instruction at the current PC.
+    ///
+    /// Returns `true` if the CPU should pause before fetching the instruction.
+    pub(crate) fn check_instruction(&mut self) -> bool {
+        if mem::take(&mut self.s.debug.paused_at_fetch) {
+            // We are resuming from this very instruction.
+            return false;
+        }
+
+        let d = &self.s.debug;
+        if d.stop.is_none() && !d.armed() {
+            return false;
+        }
+
+        let reason = self.s.debug.stop.take().or_else(|| self.instruction_stop());
+        let Some(reason) = reason else { return false };
+
+        self.s.debug.stop = Some(reason);
+        self.s.debug.paused_at_fetch = true;
+        true
+    }
+
+    fn instruction_stop(&self) -> Option<StopReason> {
+        let d = &self.s.debug;
+        let cpu = &self.s.cpu;
+        let pc = cpu.read_register(Register::PC);
+        let sp = cpu.read_register(Register::SP);
+
+        let step_done = match d.step {
+            Some(Step::Instruction) => true,
+            Some(Step::Over { ret_pc, call_sp }) => pc == ret_pc && sp >= call_sp,
+            Some(Step::Out { call_sp }) => sp > call_sp,
+            Some(Step::RunTo(addr)) => pc == addr,
+            None => false,
+        };
+        if step_done {
+            return Some(StopReason::Step);
+        }
+
+        let bank = self.s.mmu.bank_at(pc);
+        for (id, bp) in &d.breakpoints {
+            if bp.addr != pc || bp.bank.is_some_and(|b| b != bank) {
+                continue;
+            }
+            if bp.condition.map_or(true, |c| c.eval(cpu)) {
+                return Some(StopReason::Breakpoint(*id));
+            }
+        }
+
+        d.watchpoints
+            .iter()
+            .find(|(_, wp)| wp.access == Access::Execute && wp.range.contains(&pc))
+            .map(|(id, _)| StopReason::Watchpoint {
+                id: *id,
+                addr: pc,
+                access: Access::Execute,
+            })
+    }
+
+    /// Add a breakpoint at the symbol with the given name and return its ID, or `None` if there
+    /// is no such symbol.
+    pub fn add_breakpoint_at_symbol(&mut self, name: &str) -> Option<u32> {
+        let bp = self.s.debug.symbols.breakpoint(name)?;
+        Some(self.add_breakpoint(bp))
+    }
+
+    /// Record that the instruction at the current PC is about to be executed.
+    pub(crate) fn begin_instruction(&mut self) {
+        self.s.debug.inst_pc = self.s.cpu.read_register(Register::PC);
+        self.trace_instruction();
+        self.profile_instruction();
+    }
+
+    fn trace_instruction(&mut self) {
+        let Some(mut tracer) = self.s.debug.tracer.take() else { return };
+
+        if tracer.record(self.s) {
+            self.s.debug.tracer = Some(tracer);
+        }
+    }
+
+    fn profile_instruction(&mut self) {
+        let Some(mut profiler) = self.s.debug.profiler.take() else { return };
+
+        profiler.record(self.s);
+        self.s.debug.profiler = Some(profiler);
+    }
+
+    /// Stop execution after the current M-cycle, unless another stop is pending already.
+    pub(crate) fn stop(&mut self, reason: StopReason) {
+        self.s.debug.stop.get_or_insert(reason);
+    }
+
+    /// Record a memory access by the CPU, stopping execution if it hits a watchpoint.
+    pub(crate) fn check_access(&mut self, addr: u16, access: Access) {
+        let d = &mut self.s.debug;
+        if d.watchpoints.is_empty() || d.stop.is_some() {
+            return;
+        }
+
+        let hit = d
+            .watchpoints
+            .iter()
+            .find(|(_, wp)| wp.access.covers(access) && wp.range.contains(&addr));
+        if let Some((id, _)) = hit {
+            d.stop = Some(StopReason::Watchpoint {
+                id: *id,
+                addr,
+                access,
+            });
+        }
+    }
+}
+
+/// Decode the instruction at `addr`, returning it together with its length.
+pub(crate) fn decode_at(state: &State, addr: u16) -> Option<(Inst, u16)> {
+    let mut bytes = Vec::with_capacity(3);
+    for offset in 0..3 {
+        bytes.push(mmu::peek(state, addr.wrapping_add(offset)));
+        match code::decode(&bytes) {
+            Ok(inst) => return Some((inst, offset + 1)),
+            Err(code::Error::TooFewBytes) => (),
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+/// A location in memory, identified by its bank and address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Location {
+    pub bank: u16,
+    pub addr: u16,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x}:{:04x}", self.bank, self.addr)
+    }
+}
+
+/// Return the label of `addr` in the current bank mapping, if there is one.
+pub(crate) fn label_at(state: &State, addr: u16) -> Option<String> {
+    let location = Location {
+        bank: state.mmu.bank_at(addr),
+        addr,
+    };
+    state.debug.symbols.label(location)
+}
+
+/// Describe `addr` for debug output, including its label if there is one.
+pub(crate) fn describe_addr(state: &State, addr: u16) -> String {
+    match label_at(state, addr) {
+        Some(label) => format!("{addr:#06x} ({label})"),
+        None => format!("{addr:#06x}"),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Breakpoint {
+    pub addr: u16,
+    /// The memory bank the breakpoint applies to, or `None` to break in any bank.
+    pub bank: Option<u16>,
+    /// An optional condition that must be met for the breakpoint to trigger.
+    pub condition: Option<Condition>,
+}
+
+impl Breakpoint {
+    pub fn at(addr: u16) -> Self {
+        Self {
+            addr,
+            bank: None,
+            condition: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    fn eval(&self, cpu: &CpuState) -> bool {
+        let x = cpu.read_register(self.register);
+
+        use Comparison::*;
+        match self.comparison {
+            Eq => x == self.value,
+            Ne => x != self.value,
+            Lt => x < self.value,
+            Le => x <= self.value,
+            Gt => x > self.value,
+            Ge => x >= self.value,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub access: Access,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+    Execute,
+}
+
+impl Access {
+    fn covers(&self, other: Self) -> bool {
+        *self == other || (*self == Self::ReadWrite && matches!(other, Self::Read | Self::Write))
+    }
+}
+
+#[derive(Debug)]
+pub enum StopReason {
+    /// Hit the breakpoint with the given ID.
+    Breakpoint(u32),
+    /// Hit the watchpoint with the given ID.
+    Watchpoint { id: u32, addr: u16, access: Access },
+    /// Completed a step request.
+    Step,
+    /// Fetched an opcode that doesn't exist, with `IllegalOpcodePolicy::Break`.
+    IllegalOpcode { location: Location, opcode: u8 },
+    /// Completed a frame.
+    Frame(Frame),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Step {
+    Instruction,
+    Over { ret_pc: u16, call_sp: u16 },
+    Out { call_sp: u16 },
+    RunTo(u16),
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct DebugState {
+    breakpoints: BTreeMap<u32, Breakpoint>,
+    watchpoints: BTreeMap<u32, Watchpoint>,
+    last_id: u32,
+    step: Option<Step>,
+    /// A stop that has not been reported yet.
+    stop: Option<StopReason>,
+    /// Whether the CPU was paused before fetching an instruction, in the middle of an M-cycle.
+    paused_at_fetch: bool,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    symbols: Symbols,
+    /// Address of the instruction that is currently executing.
+    inst_pc: u16,
+    illegal_opcode_policy: IllegalOpcodePolicy,
+}
+
+impl DebugState {
+    fn next_id(&mut self) -> u32 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    /// Whether any stop condition is active at instruction boundaries.
+    fn armed(&self) -> bool {
+        self.step.is_some() || !self.breakpoints.is_empty() || !self.watchpoints.is_empty()
+    }
+
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) -> Option<Profiler> {
+        mem::replace(&mut self.profiler, profiler)
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn inst_pc(&self) -> u16 {
+        self.inst_pc
+    }
+
+    pub fn illegal_opcode_policy(&self) -> IllegalOpcodePolicy {
+        self.illegal_opcode_policy
+    }
+
+    pub fn set_illegal_opcode_policy(&mut self, policy: IllegalOpcodePolicy) {
+        self.illegal_opcode_policy = policy;
+    }
+
+    pub fn paused_at_fetch(&self) -> bool {
+        self.paused_at_fetch
+    }
+
+    /// Take the pending stop, if any, which also ends the current step request.
+    pub fn take_stop(&mut self) -> Option<StopReason> {
+        let stop = self.stop.take();
+        if stop.is_some() {
+            self.step = None;
+        }
+        stop
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{emulator, run_to, run_until_stop};
+    use crate::Emulator;
+
+    /// `ld b, 0; call 0x0160; inc c; jr -6`, with `inc b; ret` at 0x0160.
+    fn call_loop() -> Emulator {
+        let mut code = vec![0x06, 0x00, 0xcd, 0x60, 0x01, 0x0c, 0x18, 0xfa];
+        code.resize(0x10, 0x00);
+        code.extend([0x04, 0xc9]);
+        emulator(&code)
+    }
+
+    fn watchpoint_stop(emu: &mut Emulator) -> (u32, u16, Access) {
+        match run_until_stop(emu) {
+            StopReason::Watchpoint { id, addr, access } => (id, addr, access),
+            stop => panic!("unexpected stop: {stop:?}"),
+        }
+    }
+
+    fn pc(emu: &Emulator) -> u16 {
+        emu.cpu().pc
+    }
+
+    #[test]
+    fn breakpoint_in_other_bank_is_ignored() {
+        let mut emu = call_loop();
+        let other = emu.debugger().add_breakpoint(Breakpoint {
+            bank: Some(1),
+            ..Breakpoint::at(0x0160)
+        });
+        let current = emu.debugger().add_breakpoint(Breakpoint {
+            bank: Some(0),
+            ..Breakpoint::at(0x0155)
+        });
+
+        let stop = run_until_stop(&mut emu);
+        assert!(matches!(stop, StopReason::Breakpoint(id) if id == current));
+        assert_ne!(current, other);
+        assert_eq!(emu.cpu().b, 1);
+    }
+
+    #[test]
+    fn breakpoint_resumes_past_itself() {
+        let mut emu = call_loop();
+        let id = emu.debugger().add_breakpoint(Breakpoint::at(0x0160));
+
+        for b in 0..3 {
+            let stop = run_until_stop(&mut emu);
+            assert!(matches!(stop, StopReason::Breakpoint(stop_id) if stop_id == id));
+            assert_eq!(pc(&emu), 0x0160);
+            assert_eq!(emu.cpu().b, b);
+        }
+    }
+
+    #[test]
+    fn conditional_breakpoint() {
+        let mut emu = call_loop();
+        emu.debugger().add_breakpoint(Breakpoint {
+            condition: Some(Condition {
+                register: Register::B,
+                comparison: Comparison::Ge,
+                value: 3,
+            }),
+            ..Breakpoint::at(0x0155)
+        });
+
+        run_until_stop(&mut emu);
+        assert_eq!(emu.cpu().b, 3);
+    }
+
+    #[test]
+    fn watchpoint_covers_range_end() {
+        // ld a, 1; ld (0xc010), a; ld (0xc00f), a; jr -2
+        let code = [0x3e, 0x01, 0xea, 0x10, 0xc0, 0xea, 0x0f, 0xc0, 0x18, 0xfe];
+        let mut emu = emulator(&code);
+        let id = emu.debugger().add_watchpoint(Watchpoint {
+            range: 0xc000..=0xc00f,
+            access: Access::Write,
+        });
+
+        assert_eq!(watchpoint_stop(&mut emu), (id, 0xc00f, Access::Write));
+    }
+
+    #[test]
+    fn watchpoint_filters_access() {
+        // ld a, (0xc000); ld (0xc000), a; jr -2
+        let code = [0xfa, 0x00, 0xc0, 0xea, 0x00, 0xc0, 0x18, 0xfe];
+        let mut emu = emulator(&code);
+        emu.debugger().add_watchpoint(Watchpoint {
+            range: 0xc000..=0xc000,
+            access: Access::Write,
+        });
+
+        let (_, _, access) = watchpoint_stop(&mut emu);
+        assert_eq!(access, Access::Write);
+    }
+
+    #[test]
+    fn execute_watchpoint() {
+        let mut emu = call_loop();
+        emu.debugger().add_watchpoint(Watchpoint {
+            range: 0x0160..=0x0161,
+            access: Access::Execute,
+        });
+
+        let (_, addr, _) = watchpoint_stop(&mut emu);
+        assert_eq!(addr, 0x0160);
+    }
+
+    #[test]
+    fn step_over_call() {
+        let mut emu = call_loop();
+        run_to(&mut emu, 0x0152);
+
+        emu.debugger().step_over();
+        assert!(matches!(run_until_stop(&mut emu), StopReason::Step));
+        assert_eq!(pc(&emu), 0x0155);
+        assert_eq!(emu.cpu().b, 1);
+    }
+
+    #[test]
+    fn step_over_call_stops_at_breakpoint_in_callee() {
+        let mut emu = call_loop();
+        run_to(&mut emu, 0x0152);
+        let id = emu.debugger().add_breakpoint(Breakpoint::at(0x0161));
+
+        emu.debugger().step_over();
+        let stop = run_until_stop(&mut emu);
+        assert!(matches!(stop, StopReason::Breakpoint(stop_id) if stop_id == id));
+        assert_eq!(pc(&emu), 0x0161);
+
+        emu.debugger().step_out();
+        assert!(matches!(run_until_stop(&mut emu), StopReason::Step));
+        assert_eq!(pc(&emu), 0x0155);
+    }
+
+    #[test]
+    fn step_instruction() {
+        let mut emu = call_loop();
+        run_to(&mut emu, 0x0152);
+
+        emu.debugger().step_instruction();
+        assert!(matches!(run_until_stop(&mut emu), StopReason::Step));
+        assert_eq!(pc(&emu), 0x0160);
+    }
+}
@@ -1,6 +1,14 @@
+//! OAM DMA transfers.
+//!
+//! Reference: https://gbdev.io/pandocs/OAM_DMA_Transfer.html
+
 use crate::mmu::Mmu;
 use crate::state::State;
 
+/// Number of M-cycles between the write to the DMA register and the first transferred byte.
+const START_DELAY: u8 = 2;
+const TRANSFER_SIZE: u16 = 160;
+
 pub(crate) struct Dma<'a> {
     s: &'a mut State,
 }
@@ -13,19 +21,25 @@ impl<'a> Dma<'a> {
     pub fn step(&mut self) {
         if self.s.dma.progress.is_some() {
             self.advance();
-        } else if self.s.dma.triggered {
-            self.start();
         }
-    }
 
-    fn start(&mut self) {
-        let base = u16::from(self.s.dma.source_addr_high) << 8;
-        self.s.dma.progress = Some(Progress { base, offset: 0 });
-        self.advance();
+        // A restarted transfer only takes over once its start delay has passed, so the previous
+        // transfer keeps running in the meantime.
+        let Some(start) = &mut self.s.dma.start else { return };
+        start.delay -= 1;
+        if start.delay == 0 {
+            let base = start.base;
+            self.s.dma.start = None;
+            self.s.dma.progress = Some(Progress {
+                base,
+                offset: 0,
+                value: 0xff,
+            });
+        }
     }
 
     fn advance(&mut self) {
-        let progress = self.s.dma.progress.unwrap();
+        let Some(progress) = self.s.dma.progress else { return };
         let src = progress.base + progress.offset;
         let dst = 0xfe00 + progress.offset;
 
@@ -33,16 +47,12 @@ impl<'a> Dma<'a> {
         let value = mmu.read(src);
         mmu.write(dst, value);
 
-        let progress = &mut self.s.dma.progress.as_mut().unwrap();
-        progress.offset += 1;
-        if progress.offset == 160 {
-            self.stop();
-        }
-    }
-
-    fn stop(&mut self) {
-        self.s.dma.progress = None;
-        self.s.dma.triggered = false;
+        let offset = progress.offset + 1;
+        self.s.dma.progress = (offset < TRANSFER_SIZE).then_some(Progress {
+            offset,
+            value,
+            ..progress
+        });
     }
 }
 
@@ -50,7 +60,8 @@ impl<'a> Dma<'a> {
 #[derive(serde::Serialize, serde::Deserialize)]
 pub(crate) struct DmaState {
     source_addr_high: u8,
-    triggered: bool,
+    #[serde(default)]
+    start: Option<Start>,
     progress: Option<Progress>,
 }
 
@@ -61,13 +72,185 @@ impl DmaState {
 
     pub fn write(&mut self, value: u8) {
         self.source_addr_high = value;
-        self.triggered = true;
+
+        // Sources above the work RAM read from its echo, since OAM and the IO registers aren't
+        // reachable by the DMA.
+        let base = match u16::from(value) << 8 {
+            addr @ 0xe000.. => addr - 0x2000,
+            addr => addr,
+        };
+        self.start = Some(Start {
+            base,
+            delay: START_DELAY,
+        });
+    }
+
+    /// Set the DMA register without starting a transfer.
+    pub fn poke(&mut self, value: u8) {
+        self.source_addr_high = value;
+    }
+
+    /// Return the value the CPU reads from `addr` instead of the memory contents, if a running
+    /// transfer blocks the access.
+    ///
+    /// During a transfer, OAM reads return 0xff, and reads from the bus the transfer reads from
+    /// return the byte the transfer last read.
+    pub fn blocked_read(&self, addr: u16) -> Option<u8> {
+        let progress = self.progress?;
+        if is_oam(addr) {
+            Some(0xff)
+        } else if progress.conflicts(addr) {
+            Some(progress.value)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether a running transfer blocks CPU writes to `addr`.
+    pub fn blocks_write(&self, addr: u16) -> bool {
+        self.progress
+            .is_some_and(|p| is_oam(addr) || p.conflicts(addr))
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Start {
+    base: u16,
+    /// Remaining M-cycles until the transfer starts.
+    delay: u8,
+}
+
 #[derive(Clone, Copy, Debug)]
 #[derive(serde::Serialize, serde::Deserialize)]
 struct Progress {
     base: u16,
     offset: u16,
+    /// The byte transferred last, which the CPU sees on bus conflicts.
+    #[serde(default)]
+    value: u8,
+}
+
+impl Progress {
+    /// Returns whether a CPU access to `addr` uses the same bus as the transfer.
+    fn conflicts(&self, addr: u16) -> bool {
+        bus(addr).is_some_and(|b| Some(b) == bus(self.base))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Bus {
+    /// The cartridge and work RAM.
+    External,
+    Video,
+}
+
+fn bus(addr: u16) -> Option<Bus> {
+    match addr {
+        0x0000..=0x7fff | 0xa000..=0xfdff => Some(Bus::External),
+        0x8000..=0x9fff => Some(Bus::Video),
+        _ => None,
+    }
+}
+
+fn is_oam(addr: u16) -> bool {
+    (0xfe00..=0xfeff).contains(&addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mmu, testing};
+
+    /// Fill the work RAM with a pattern that differs between 0xc000 and 0xd000.
+    fn state() -> State {
+        let mut s = testing::state(&[]);
+        let mut mmu = Mmu::new(&mut s);
+        for i in 0..TRANSFER_SIZE {
+            mmu.write(0xc000 + i, pattern(0xc000, i));
+            mmu.write(0xd000 + i, pattern(0xd000, i));
+        }
+        s
+    }
+
+    fn pattern(base: u16, offset: u16) -> u8 {
+        (base >> 8) as u8 ^ offset as u8 ^ 0x5a
+    }
+
+    fn start(s: &mut State, source: u8) {
+        Mmu::new(s).write(0xff46, source);
+    }
+
+    fn step(s: &mut State, cycles: u16) {
+        (0..cycles).for_each(|_| Dma::new(s).step());
+    }
+
+    fn oam(s: &State, offset: u16) -> u8 {
+        mmu::peek(s, 0xfe00 + offset)
+    }
+
+    #[test]
+    fn first_byte_follows_start_delay() {
+        let mut s = state();
+        start(&mut s, 0xc0);
+
+        step(&mut s, START_DELAY.into());
+        assert_eq!(oam(&s, 0), 0);
+
+        step(&mut s, 1);
+        assert_eq!(oam(&s, 0), pattern(0xc000, 0));
+        assert_eq!(oam(&s, 1), 0);
+
+        step(&mut s, TRANSFER_SIZE - 1);
+        assert!((0..TRANSFER_SIZE).all(|i| oam(&s, i) == pattern(0xc000, i)));
+        assert!(s.dma.progress.is_none());
+    }
+
+    #[test]
+    fn transfer_blocks_oam_and_source_bus() {
+        let mut s = state();
+        start(&mut s, 0xc0);
+        step(&mut s, START_DELAY.into());
+        assert_eq!(s.dma.blocked_read(0xfe00), Some(0xff));
+
+        step(&mut s, 1);
+        assert_eq!(s.dma.blocked_read(0xfe00), Some(0xff));
+        assert_eq!(s.dma.blocked_read(0x0150), Some(pattern(0xc000, 0)));
+        assert_eq!(s.dma.blocked_read(0x8000), None);
+        assert_eq!(s.dma.blocked_read(0xff80), None);
+        assert!(s.dma.blocks_write(0xd000));
+        assert!(!s.dma.blocks_write(0x9800));
+
+        step(&mut s, TRANSFER_SIZE - 1);
+        assert_eq!(s.dma.blocked_read(0xfe00), None);
+        assert!(!s.dma.blocks_write(0xd000));
+    }
+
+    #[test]
+    fn restart_takes_over_after_start_delay() {
+        let mut s = state();
+        start(&mut s, 0xc0);
+        step(&mut s, u16::from(START_DELAY) + 10);
+
+        // The first transfer keeps copying until the new one starts.
+        start(&mut s, 0xd0);
+        step(&mut s, START_DELAY.into());
+        assert_eq!(oam(&s, 0), pattern(0xc000, 0));
+        assert_eq!(oam(&s, 11), pattern(0xc000, 11));
+        assert_eq!(oam(&s, 12), 0);
+
+        step(&mut s, 1);
+        assert_eq!(oam(&s, 0), pattern(0xd000, 0));
+        assert_eq!(oam(&s, 1), pattern(0xc000, 1));
+    }
+
+    #[test]
+    fn echo_sources_read_work_ram() {
+        let mut s = state();
+        start(&mut s, 0xf0);
+        assert_eq!(s.dma.read(), 0xf0);
+
+        step(&mut s, u16::from(START_DELAY) + TRANSFER_SIZE);
+        assert!((0..TRANSFER_SIZE).all(|i| oam(&s, i) == pattern(0xd000, i)));
+    }
 }
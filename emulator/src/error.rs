@@ -0,0 +1,67 @@
+//! Errors caused by the emulated program or the files it was loaded from, rather than by bugs in
+//! the emulator.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::debugger::Location;
+
+#[derive(Debug)]
+pub enum EmulatorError {
+    /// The CPU fetched an opcode that doesn't exist.
+    InvalidOpcode { location: Location, opcode: u8 },
+    /// The cartridge requires hardware that isn't emulated.
+    Unsupported(String),
+    /// The ROM or cartridge RAM is malformed.
+    InvalidCartridge(anyhow::Error),
+    /// The savestate can't be decoded.
+    CorruptedSavestate(anyhow::Error),
+}
+
+impl EmulatorError {
+    /// Classify an error from loading a cartridge.
+    pub(crate) fn cartridge(error: anyhow::Error) -> Self {
+        match error.downcast::<Self>() {
+            Ok(error) => error,
+            Err(error) => Self::InvalidCartridge(error),
+        }
+    }
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use EmulatorError::*;
+        match self {
+            InvalidOpcode { location, opcode } => {
+                write!(f, "invalid opcode {opcode:#04x} at {location}")
+            }
+            Unsupported(feature) => write!(f, "unsupported hardware: {feature}"),
+            InvalidCartridge(error) => write!(f, "invalid cartridge: {error:#}"),
+            CorruptedSavestate(error) => write!(f, "corrupted savestate: {error:#}"),
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
+
+/// What to do when the CPU fetches an opcode that doesn't exist.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IllegalOpcodePolicy {
+    /// Lock up the CPU until the next reset, like real hardware.
+    #[default]
+    Lock,
+    /// Stop in the debugger before the illegal opcode.
+    Break,
+}
+
+impl FromStr for IllegalOpcodePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lock" => Ok(Self::Lock),
+            "break" => Ok(Self::Break),
+            _ => Err(format!("invalid illegal opcode policy: {s}")),
+        }
+    }
+}
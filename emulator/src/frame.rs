@@ -13,6 +13,8 @@ pub struct Frame {
 impl Frame {
     pub const WIDTH: u32 = 160;
     pub const HEIGHT: u32 = 144;
+    /// Number of dots (4 MHz clock cycles) the PPU takes to produce one frame.
+    pub const DOTS: u32 = 70224;
 
     const PIXEL_COUNT: usize = (Self::WIDTH * Self::HEIGHT) as usize;
 
@@ -48,6 +50,42 @@ impl Default for Frame {
     }
 }
 
+/// An RGBA image of PPU inputs, for debugging.
+#[derive(Clone, Debug)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+impl Image {
+    pub(crate) fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            rgba: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Return the image's pixels as RGBA bytes, row by row.
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+
+    pub(crate) fn set_pixel(&mut self, x: u32, y: u32, rgba: [u8; 4]) {
+        let i = ((y * self.width + x) * 4) as usize;
+        self.rgba[i..i + 4].copy_from_slice(&rgba);
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 #[derive(serde::Serialize, serde::Deserialize)]
 pub(crate) enum Color {
@@ -56,16 +94,19 @@ pub(crate) enum Color {
     Light,
     Dark,
     Black,
+    /// A color outside the DMG palette, used to highlight debug information.
+    Highlight,
 }
 
 impl Color {
-    fn rgba(&self) -> [u8; 4] {
+    pub(crate) fn rgba(&self) -> [u8; 4] {
         use Color::*;
         match self {
             White => [0xe0, 0xe0, 0xe0, 0xff],
             Light => [0xa0, 0xa0, 0xa0, 0xff],
             Dark => [0x60, 0x60, 0x60, 0xff],
             Black => [0x20, 0x20, 0x20, 0xff],
+            Highlight => [0xe0, 0x40, 0x40, 0xff],
         }
     }
 }
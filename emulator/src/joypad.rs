@@ -47,6 +47,11 @@ impl JoypadState {
         !value
     }
 
+    /// Returns whether any input line of the selected buttons is low.
+    pub fn any_line_low(&self) -> bool {
+        self.read_p1().bits(0..=3) != 0x0f
+    }
+
     pub fn write_p1(&mut self, value: u8) {
         self.select.direction = !value.bit(4);
         self.select.action = !value.bit(5);
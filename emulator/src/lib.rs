@@ -1,3 +1,4 @@
+use std::io::Write;
 use std::path::Path;
 
 use anyhow::Result;
@@ -6,51 +7,222 @@ use log::{error, info};
 use crate::cpu::Cpu;
 use crate::dma::Dma;
 use crate::joypad::Joypad;
+use crate::mmu::Mmu;
 use crate::ppu::Ppu;
+use crate::profiler::Profiler;
 use crate::state::State;
 use crate::timer::Timer;
+use crate::trace::Tracer;
 
 mod bits;
 mod cartridge;
 mod cpu;
+mod debugger;
 mod dma;
+mod error;
 mod frame;
 mod joypad;
 mod mmu;
 mod ppu;
+mod profiler;
 mod state;
+mod symbols;
+#[cfg(test)]
+mod testing;
 mod timer;
+mod trace;
 
-pub use frame::Frame;
+pub use cpu::{CpuSnapshot, Register};
+pub use debugger::{
+    Access, Breakpoint, Comparison, Condition, Debugger, Location, StopReason, Watchpoint,
+};
+pub use error::{EmulatorError, IllegalOpcodePolicy};
+pub use frame::{Frame, Image};
 pub use joypad::Button;
+pub use mmu::Region;
+pub use ppu::{Attributes, Flags, Layers, Object, TileMap};
+pub use profiler::Profile;
+pub use symbols::Symbols;
+pub use trace::TraceFormat;
 
 pub struct Emulator {
     state: State,
 }
 
 impl Emulator {
-    pub fn load(rom_or_save: Vec<u8>, ram: Option<Vec<u8>>) -> Result<Self> {
+    /// Frequency of the system clock, in Hz.
+    pub const CLOCK_RATE: u32 = 4194304;
+    /// Number of frames produced per second.
+    pub const FRAME_RATE: f64 = Self::CLOCK_RATE as f64 / Frame::DOTS as f64;
+
+    pub fn load(rom_or_save: Vec<u8>, ram: Option<Vec<u8>>) -> Result<Self, EmulatorError> {
         let state = State::load(rom_or_save, ram)?;
 
         Ok(Self { state })
     }
 
-    pub fn render_frame(&mut self) -> Result<Frame> {
-        let s = &mut self.state;
+    /// Run until the next frame is complete, ignoring any debugger stops.
+    ///
+    /// Fails if the CPU hits an illegal opcode with `IllegalOpcodePolicy::Break`.
+    pub fn render_frame(&mut self) -> Result<Frame, EmulatorError> {
         loop {
-            Timer::new(s).step();
-            Cpu::new(s).step()?;
-            Dma::new(s).step();
-
-            let mut ppu = Ppu::new(s);
-            (0..4).for_each(|_| ppu.step());
+            match self.run_until_break() {
+                StopReason::Frame(frame) => return Ok(frame),
+                StopReason::IllegalOpcode { location, opcode } => {
+                    return Err(EmulatorError::InvalidOpcode { location, opcode });
+                }
+                _ => (),
+            }
+        }
+    }
 
-            if let Some(frame) = ppu.take_frame() {
-                return Ok(frame);
+    /// Run until the debugger stops execution or the next frame is complete.
+    pub fn run_until_break(&mut self) -> StopReason {
+        loop {
+            if let Some(frame) = self.step() {
+                return StopReason::Frame(frame);
+            }
+            if let Some(stop) = self.state.debug.take_stop() {
+                return stop;
             }
         }
     }
 
+    /// Choose what happens when the CPU fetches an opcode that doesn't exist.
+    pub fn set_illegal_opcode_policy(&mut self, policy: IllegalOpcodePolicy) {
+        self.state.debug.set_illegal_opcode_policy(policy);
+    }
+
+    pub fn cpu(&self) -> CpuSnapshot {
+        self.state.cpu.snapshot()
+    }
+
+    /// Overwrite the CPU registers.
+    ///
+    /// Changes to the PC take effect once the current instruction has completed, so this is best
+    /// used while the debugger is stopped at an instruction boundary.
+    pub fn set_cpu(&mut self, cpu: &CpuSnapshot) {
+        self.state.cpu.restore(cpu);
+    }
+
+    /// Read the byte at `addr`, without triggering any side effects.
+    pub fn peek(&self, addr: u16) -> u8 {
+        mmu::peek(&self.state, addr)
+    }
+
+    /// Write the byte at `addr`, without triggering any side effects.
+    ///
+    /// ROM writes patch the mapped ROM bank instead of switching banks, and IO register writes only
+    /// store the value, without resetting the divider, ticking TIMA, raising interrupts or starting
+    /// a DMA transfer.
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        Mmu::new(&mut self.state).poke(addr, value);
+    }
+
+    /// Decode the instruction at `addr`, returning it together with its length.
+    pub fn decode(&self, addr: u16) -> Option<(code::Inst, u16)> {
+        debugger::decode_at(&self.state, addr)
+    }
+
+    /// Return the number of banks in the given memory region.
+    pub fn banks(&self, region: Region) -> u16 {
+        self.state.mmu.banks(region)
+    }
+
+    /// Read a byte from the given memory region, or `None` if the location doesn't exist.
+    pub fn peek_banked(&self, region: Region, bank: u16, offset: u16) -> Option<u8> {
+        self.state.mmu.peek_banked(region, bank, offset)
+    }
+
+    /// Write a byte into the given memory region, returning whether the location exists.
+    pub fn poke_banked(&mut self, region: Region, bank: u16, offset: u16, value: u8) -> bool {
+        self.state.mmu.poke_banked(region, bank, offset, value)
+    }
+
+    pub fn layers(&self) -> Layers {
+        self.state.ppu.layers()
+    }
+
+    /// Enable or disable the layers drawn by the PPU.
+    pub fn set_layers(&mut self, layers: Layers) {
+        self.state.ppu.set_layers(layers);
+    }
+
+    /// Render the 384 tiles in VRAM, 16 tiles per row.
+    pub fn render_tiles(&self) -> Image {
+        ppu::render_tiles(&self.state)
+    }
+
+    /// Render a 32x32 tile map, with the SCX/SCY viewport outlined.
+    pub fn render_tile_map(&self, map: TileMap) -> Image {
+        ppu::render_tile_map(&self.state, map)
+    }
+
+    /// Decode and render the 40 OAM entries.
+    pub fn objects(&self) -> Vec<Object> {
+        ppu::objects(&self.state)
+    }
+
+    /// Start writing a trace line for each executed instruction to `out`.
+    pub fn start_trace(&mut self, out: Box<dyn Write>, format: TraceFormat) {
+        let tracer = Tracer::new(out, format);
+        self.state.debug.set_tracer(Some(tracer));
+    }
+
+    pub fn stop_trace(&mut self) {
+        self.state.debug.set_tracer(None);
+    }
+
+    /// Start counting the M-cycles spent in each instruction and routine.
+    pub fn start_profile(&mut self) {
+        self.state.debug.set_profiler(Some(Profiler::default()));
+    }
+
+    /// Stop profiling and return the collected profile, if profiling was started.
+    pub fn stop_profile(&mut self) -> Option<Profile> {
+        let profiler = self.state.debug.set_profiler(None)?;
+        Some(profiler.finish(self.state.cycles, self.state.debug.symbols()))
+    }
+
+    /// Use the given symbols to label addresses in debug output.
+    pub fn load_symbols(&mut self, symbols: Symbols) {
+        self.state.debug.set_symbols(symbols);
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        self.state.debug.symbols()
+    }
+
+    /// Return the label of `addr` in the current bank mapping, as `label+offset`.
+    pub fn label(&self, addr: u16) -> Option<String> {
+        debugger::label_at(&self.state, addr)
+    }
+
+    pub fn debugger(&mut self) -> Debugger<'_> {
+        Debugger::new(&mut self.state)
+    }
+
+    /// Emulate a single M-cycle.
+    fn step(&mut self) -> Option<Frame> {
+        let s = &mut self.state;
+
+        // When resuming from a pause in the middle of the cycle, the timer already stepped. In STOP
+        // mode, the divider doesn't run.
+        if !s.debug.paused_at_fetch() && !s.cpu.stopped() {
+            Timer::new(s).step();
+        }
+        Cpu::new(s).step();
+        if s.debug.paused_at_fetch() {
+            return None;
+        }
+        Dma::new(s).step();
+        s.cycles += 1;
+
+        let mut ppu = Ppu::new(s);
+        (0..4).for_each(|_| ppu.step());
+        ppu.take_frame()
+    }
+
     pub fn press_button(&mut self, button: Button) {
         Joypad::new(&mut self.state).press_button(button);
     }
@@ -73,6 +245,17 @@ impl Emulator {
         }
     }
 
+    pub fn dump_state(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.state.dump_save(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Returns whether the cartridge RAM was written since the last call.
+    pub fn take_ram_dirty(&mut self) -> bool {
+        self.state.mmu.take_ram_dirty()
+    }
+
     pub fn dump_ram(&self) -> Result<Vec<u8>> {
         let mut buf = Vec::new();
         self.state.dump_ram(&mut buf)?;
@@ -0,0 +1,112 @@
+//! The IO registers at 0xff00-0xff7f.
+//!
+//! Reference: https://gbdev.io/pandocs/Hardware_Reg_List.html
+
+use std::ops::RangeInclusive;
+
+use log::debug;
+
+use crate::state::State;
+
+struct Register {
+    addrs: RangeInclusive<u16>,
+    /// Bits that are not implemented and always read as 1.
+    unused: u8,
+    read: fn(&State) -> u8,
+    write: fn(&mut State, u8),
+    /// Set the register without the side effects of a write, for debugging.
+    poke: fn(&mut State, u8),
+}
+
+/// `reg!(addr, unused bits, component, read method, write method, poke method)` defines a register
+/// backed by the given component of the state, `reg!(addrs, unmapped)` defines registers of
+/// hardware we don't emulate.
+macro_rules! reg {
+    ($addr:literal, $unused:literal, $($dev:ident).+, $read:ident, read_only) => {
+        Register {
+            addrs: $addr..=$addr,
+            unused: $unused,
+            read: |s| s.$($dev).+.$read(),
+            write: |_, _| (),
+            poke: |_, _| (),
+        }
+    };
+    ($addr:literal, $unused:literal, $($dev:ident).+, $read:ident, $write:ident, $poke:ident) => {
+        Register {
+            addrs: $addr..=$addr,
+            unused: $unused,
+            read: |s| s.$($dev).+.$read(),
+            write: |s, v| s.$($dev).+.$write(v),
+            poke: |s, v| s.$($dev).+.$poke(v),
+        }
+    };
+    ($addrs:expr, unmapped) => {
+        Register {
+            addrs: $addrs,
+            unused: 0xff,
+            read: |_| 0xff,
+            write: |_, _| (),
+            poke: |_, _| (),
+        }
+    };
+}
+
+/// The mapped IO registers, sorted by address.
+static REGISTERS: [Register; 21] = [
+    reg!(0xff00, 0xc0, joypad, read_p1, write_p1, write_p1),
+    reg!(0xff01..=0xff02, unmapped), // serial transfer
+    reg!(0xff04, 0x00, timer, read_div, write_div, poke_div),
+    reg!(0xff05, 0x00, timer, read_tima, write_tima, poke_tima),
+    reg!(0xff06, 0x00, timer, read_tma, write_tma, poke_tma),
+    reg!(0xff07, 0xf8, timer, read_tac, write_tac, poke_tac),
+    reg!(
+        0xff0f,
+        0xe0,
+        cpu.interrupts,
+        read_flag,
+        write_flag,
+        write_flag
+    ),
+    reg!(0xff10..=0xff3f, unmapped), // audio
+    reg!(0xff40, 0x00, ppu, read_lcdc, write_lcdc, write_lcdc),
+    reg!(0xff41, 0x80, ppu, read_stat, write_stat, poke_stat),
+    reg!(0xff42, 0x00, ppu, read_scy, write_scy, write_scy),
+    reg!(0xff43, 0x00, ppu, read_scx, write_scx, write_scx),
+    reg!(0xff44, 0x00, ppu, read_ly, read_only),
+    reg!(0xff45, 0x00, ppu, read_lyc, write_lyc, write_lyc),
+    reg!(0xff46, 0x00, dma, read, write, poke),
+    reg!(0xff47, 0x00, ppu, read_bgp, write_bgp, write_bgp),
+    reg!(0xff48, 0x00, ppu, read_obp0, write_obp0, write_obp0),
+    reg!(0xff49, 0x00, ppu, read_obp1, write_obp1, write_obp1),
+    reg!(0xff4a, 0x00, ppu, read_wy, write_wy, write_wy),
+    reg!(0xff4b, 0x00, ppu, read_wx, write_wx, write_wx),
+    reg!(0xff50..=0xff50, unmapped), // boot ROM disable
+];
+
+fn register(addr: u16) -> Option<&'static Register> {
+    let i = REGISTERS.partition_point(|r| *r.addrs.end() < addr);
+    REGISTERS.get(i).filter(|r| r.addrs.contains(&addr))
+}
+
+/// Read the IO register at `addr`. Unmapped registers read as 0xff.
+pub(super) fn read(state: &State, addr: u16) -> u8 {
+    match register(addr) {
+        Some(r) => (r.read)(state) | r.unused,
+        None => 0xff,
+    }
+}
+
+/// Write the IO register at `addr`. Writes to unmapped registers are ignored.
+pub(super) fn write(state: &mut State, addr: u16, value: u8) {
+    match register(addr) {
+        Some(r) => (r.write)(state, value),
+        None => debug!("write to unmapped IO register {addr:#x}: {value:#x}"),
+    }
+}
+
+/// Set the IO register at `addr`, without triggering the side effects of a write.
+pub(super) fn poke(state: &mut State, addr: u16, value: u8) {
+    if let Some(r) = register(addr) {
+        (r.poke)(state, value);
+    }
+}
@@ -1,5 +1,6 @@
 use std::cmp;
 use std::io::Write;
+use std::mem;
 
 use anyhow::{bail, Result};
 use log::warn;
@@ -16,6 +17,7 @@ pub(in crate::mmu) struct Mapper {
     rom: memory::Banked<ROM_BANK_SIZE>,
     ram: memory::Banked<RAM_BANK_SIZE>,
     rom_bank_nr: u8,
+    ram_dirty: bool,
 }
 
 impl Mapper {
@@ -47,6 +49,40 @@ impl Mapper {
         }
     }
 
+    pub(super) fn rom_bank(&self) -> u16 {
+        self.rom_bank_nr.into()
+    }
+
+    pub(super) fn rom_banks(&self) -> u16 {
+        self.rom.banks() as u16
+    }
+
+    pub(super) fn ram_banks(&self) -> u16 {
+        self.ram.banks() as u16
+    }
+
+    pub(super) fn peek_rom(&self, bank: u16, offset: u16) -> Option<u8> {
+        self.rom.get_checked(bank, offset)
+    }
+
+    pub(super) fn poke_rom(&mut self, bank: u16, offset: u16, value: u8) -> bool {
+        self.rom
+            .get_checked_mut(bank, offset)
+            .map(|v| *v = value)
+            .is_some()
+    }
+
+    pub(super) fn peek_ram(&self, bank: u16, offset: u16) -> Option<u8> {
+        self.ram.get_checked(bank, offset)
+    }
+
+    pub(super) fn poke_ram(&mut self, bank: u16, offset: u16, value: u8) -> bool {
+        let Some(v) = self.ram.get_checked_mut(bank, offset) else { return false };
+        *v = value;
+        self.ram_dirty = true;
+        true
+    }
+
     pub(super) fn read_ram(&self, addr: u16) -> u8 {
         self.ram.get(0, addr).unwrap_or_else(|| {
             warn!("invalid RAM read addr: {addr:#x}");
@@ -56,11 +92,18 @@ impl Mapper {
 
     pub(super) fn write_ram(&mut self, addr: u16, value: u8) {
         match self.ram.get_mut(0, addr) {
-            Some(v) => *v = value,
+            Some(v) => {
+                *v = value;
+                self.ram_dirty = true;
+            }
             None => warn!("invalid RAM write addr: {addr:#x}"),
         }
     }
 
+    pub(super) fn take_ram_dirty(&mut self) -> bool {
+        mem::take(&mut self.ram_dirty)
+    }
+
     pub(super) fn dump_ram<W: Write>(&self, mut w: W) -> Result<()> {
         w.write_all(self.ram.as_slice())?;
         Ok(())
@@ -84,5 +127,6 @@ pub(super) fn load(rom: Memory, ram: Memory) -> Result<Mapper> {
         rom,
         ram,
         rom_bank_nr: 1,
+        ram_dirty: false,
     })
 }
@@ -31,6 +31,65 @@ impl Mapper {
         }
     }
 
+    /// Return the number of the ROM bank mapped at 0x4000-0x7fff.
+    pub(super) fn rom_bank(&self) -> u16 {
+        match self {
+            Self::RomOnly(_) => 1,
+            Self::Mbc1(m) => m.rom_bank(),
+        }
+    }
+
+    /// Return the number of the RAM bank mapped at 0xa000-0xbfff.
+    pub(super) fn ram_bank(&self) -> u16 {
+        0
+    }
+
+    pub(super) fn rom_banks(&self) -> u16 {
+        match self {
+            Self::RomOnly(m) => m.rom_banks(),
+            Self::Mbc1(m) => m.rom_banks(),
+        }
+    }
+
+    pub(super) fn ram_banks(&self) -> u16 {
+        match self {
+            Self::RomOnly(_) => 0,
+            Self::Mbc1(m) => m.ram_banks(),
+        }
+    }
+
+    /// Read a ROM byte, bypassing the current bank mapping.
+    pub(super) fn peek_rom(&self, bank: u16, offset: u16) -> Option<u8> {
+        match self {
+            Self::RomOnly(m) => m.peek_rom(bank, offset),
+            Self::Mbc1(m) => m.peek_rom(bank, offset),
+        }
+    }
+
+    /// Patch a ROM byte, bypassing the current bank mapping.
+    pub(super) fn poke_rom(&mut self, bank: u16, offset: u16, value: u8) -> bool {
+        match self {
+            Self::RomOnly(m) => m.poke_rom(bank, offset, value),
+            Self::Mbc1(m) => m.poke_rom(bank, offset, value),
+        }
+    }
+
+    /// Read a RAM byte, bypassing the current bank mapping.
+    pub(super) fn peek_ram(&self, bank: u16, offset: u16) -> Option<u8> {
+        match self {
+            Self::RomOnly(_) => None,
+            Self::Mbc1(m) => m.peek_ram(bank, offset),
+        }
+    }
+
+    /// Write a RAM byte, bypassing the current bank mapping.
+    pub(super) fn poke_ram(&mut self, bank: u16, offset: u16, value: u8) -> bool {
+        match self {
+            Self::RomOnly(_) => false,
+            Self::Mbc1(m) => m.poke_ram(bank, offset, value),
+        }
+    }
+
     pub(super) fn read_ram(&self, addr: u16) -> u8 {
         match self {
             Self::RomOnly(_) => {
@@ -48,6 +107,13 @@ impl Mapper {
         }
     }
 
+    pub(super) fn take_ram_dirty(&mut self) -> bool {
+        match self {
+            Self::RomOnly(_) => false,
+            Self::Mbc1(m) => m.take_ram_dirty(),
+        }
+    }
+
     pub(super) fn dump_ram<W: Write>(&self, w: W) -> Result<()> {
         match self {
             Self::RomOnly(_) => Ok(()),
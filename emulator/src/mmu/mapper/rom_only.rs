@@ -6,6 +6,7 @@ use crate::mmu::memory::Memory;
 use crate::mmu::KB;
 
 const ROM_SIZE: usize = 32 * KB;
+const ROM_BANK_SIZE: u16 = 16 * KB as u16;
 
 #[derive(Debug)]
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -20,6 +21,27 @@ impl Mapper {
             0xff
         })
     }
+
+    pub(super) fn rom_banks(&self) -> u16 {
+        (ROM_SIZE / usize::from(ROM_BANK_SIZE)) as u16
+    }
+
+    pub(super) fn peek_rom(&self, bank: u16, offset: u16) -> Option<u8> {
+        let idx = bank_index(bank, offset)?;
+        self.rom.get(idx)
+    }
+
+    pub(super) fn poke_rom(&mut self, bank: u16, offset: u16, value: u8) -> bool {
+        let Some(idx) = bank_index(bank, offset) else { return false };
+        self.rom.get_mut(idx).map(|v| *v = value).is_some()
+    }
+}
+
+fn bank_index(bank: u16, offset: u16) -> Option<usize> {
+    if offset >= ROM_BANK_SIZE {
+        return None;
+    }
+    Some(usize::from(bank) * usize::from(ROM_BANK_SIZE) + usize::from(offset))
 }
 
 pub(super) fn load(rom: Memory) -> Result<Mapper> {
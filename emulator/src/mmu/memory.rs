@@ -69,6 +69,18 @@ impl<const N: u16> Banked<N> {
         self.0.get_mut(Self::idx(bank, offset))
     }
 
+    /// Like `get`, but checks that `offset` lies within the bank.
+    pub(super) fn get_checked(&self, bank: u16, offset: u16) -> Option<u8> {
+        let bank = u8::try_from(bank).ok()?;
+        (offset < N).then(|| self.get(bank, offset)).flatten()
+    }
+
+    /// Like `get_mut`, but checks that `offset` lies within the bank.
+    pub(super) fn get_checked_mut(&mut self, bank: u16, offset: u16) -> Option<&mut u8> {
+        let bank = u8::try_from(bank).ok()?;
+        (offset < N).then(|| self.get_mut(bank, offset)).flatten()
+    }
+
     pub(super) fn as_slice(&self) -> &[u8] {
         self.0.as_slice()
     }
@@ -1,16 +1,19 @@
 use std::io::Write;
 
 use anyhow::{bail, Context, Result};
-use log::{trace, warn};
 
 use crate::cartridge::{self, MapperType};
+use crate::error::EmulatorError;
 use crate::state::State;
 
 use self::mapper::Mapper;
 use self::memory::Memory;
 
+mod io;
 mod mapper;
 mod memory;
+#[cfg(test)]
+mod tests;
 
 const KB: usize = 1024;
 
@@ -49,8 +52,11 @@ pub(crate) fn load_cartridge(rom: Vec<u8>, ram: Option<Vec<u8>>) -> Result<MmuSt
     let mapper = match mapper_type {
         MapperType::None => mapper::load_rom_only(rom)?,
         MapperType::Mbc1 => mapper::load_mbc1(rom, ram)?,
-        MapperType::Mbc3 => todo!("MBC3"),
-        MapperType::Unsupported(code) => bail!("unsupported cartridge type: {code:#x}"),
+        MapperType::Mbc3 => bail!(EmulatorError::Unsupported("MBC3 cartridges".into())),
+        MapperType::Unsupported(code) => {
+            let feature = format!("cartridge type {code:#x}");
+            bail!(EmulatorError::Unsupported(feature))
+        }
     };
 
     Ok(MmuState::new(mapper))
@@ -66,78 +72,146 @@ impl<'a> Mmu<'a> {
     }
 
     pub fn read(&self, addr: u16) -> u8 {
-        let data = match addr {
+        match addr {
             0x0000..=0x7fff => self.s.mmu.mapper.read_rom(addr),
             0x8000..=0x9fff => self.s.mmu.video_ram[addr - 0x8000],
             0xa000..=0xbfff => self.s.mmu.mapper.read_ram(addr - 0xa000),
             0xc000..=0xdfff => self.s.mmu.work_ram[addr - 0xc000],
+            0xe000..=0xfdff => self.s.mmu.work_ram[addr - 0xe000], // echo RAM
             0xfe00..=0xfe9f => self.s.mmu.oam[addr - 0xfe00],
-            0xff00 => self.s.joypad.read_p1(),
-            0xff04 => self.s.timer.read_div(),
-            0xff05 => self.s.timer.read_tima(),
-            0xff06 => self.s.timer.read_tma(),
-            0xff07 => self.s.timer.read_tac(),
-            0xff0f => self.s.cpu.interrupts.read_flag(),
-            0xff10..=0xff3f => 0xff, // audio registers
-            0xff40 => self.s.ppu.read_lcdc(),
-            0xff41 => self.s.ppu.read_stat(),
-            0xff42 => self.s.ppu.read_scy(),
-            0xff43 => self.s.ppu.read_scx(),
-            0xff44 => self.s.ppu.read_ly(),
-            0xff45 => self.s.ppu.read_lyc(),
-            0xff46 => self.s.dma.read(),
-            0xff47 => self.s.ppu.read_bgp(),
-            0xff48 => self.s.ppu.read_obp0(),
-            0xff49 => self.s.ppu.read_obp1(),
-            0xff4a => self.s.ppu.read_wy(),
-            0xff4b => self.s.ppu.read_wx(),
-            0xff50 => 0xff, // boot ROM enable
+            0xfea0..=0xfeff => self.read_unusable(),
+            0xff00..=0xff7f => io::read(self.s, addr),
             0xff80..=0xfffe => self.s.mmu.high_ram[addr - 0xff80],
             0xffff => self.s.cpu.interrupts.read_enable(),
-            _ => {
-                warn!("invalid memory read addr: {addr:#x}");
-                0xff
-            }
-        };
+        }
+    }
 
-        trace!("read: {addr:#04x} -> {data:#02x}");
-        data
+    /// Read the value at `addr` on behalf of the CPU.
+    ///
+    /// While the PPU uses VRAM or OAM, the CPU can't access them and reads 0xff instead.
+    pub fn cpu_read(&self, addr: u16) -> u8 {
+        if self.ppu_blocks(addr) {
+            0xff
+        } else {
+            self.read(addr)
+        }
     }
 
-    pub fn write(&mut self, addr: u16, value: u8) {
-        trace!("write: {addr:#04x} <- {value:#02x}");
+    /// Read from the unusable area after OAM.
+    ///
+    /// On the DMG, which is the model we emulate, reads return 0x00, or 0xff while the PPU blocks
+    /// OAM. CGB models return other values instead, like repeating the address nibbles.
+    fn read_unusable(&self) -> u8 {
+        if self.s.ppu.oam_blocked() {
+            0xff
+        } else {
+            0x00
+        }
+    }
+
+    /// Read the value at `addr`, without any side effects.
+    pub fn peek(&self, addr: u16) -> u8 {
+        peek(self.s, addr)
+    }
+
+    /// Write `value` to `addr`, without any side effects.
+    ///
+    /// Writes to ROM patch the ROM contents, rather than performing bank switches. Writes to IO
+    /// registers only store the value, e.g. DIV writes don't reset the divider and DMA writes don't
+    /// start a transfer.
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        if let Some((region, bank, offset)) = self.s.mmu.locate(addr) {
+            self.s.mmu.poke_banked(region, bank, offset, value);
+            return;
+        }
 
+        match addr {
+            0xff00..=0xff7f => io::poke(self.s, addr, value),
+            0xffff => self.s.cpu.interrupts.write_enable(value),
+            _ => (),
+        }
+    }
+
+    /// Write `value` to `addr` on behalf of the CPU.
+    ///
+    /// While the PPU uses VRAM or OAM, the CPU can't access them and writes are ignored.
+    pub fn cpu_write(&mut self, addr: u16, value: u8) {
+        if !self.ppu_blocks(addr) {
+            self.write(addr, value);
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
         match addr {
             0x0000..=0x7fff => self.s.mmu.mapper.write_rom(addr, value),
             0x8000..=0x9fff => self.s.mmu.video_ram[addr - 0x8000] = value,
             0xa000..=0xbfff => self.s.mmu.mapper.write_ram(addr - 0xa000, value),
             0xc000..=0xdfff => self.s.mmu.work_ram[addr - 0xc000] = value,
+            0xe000..=0xfdff => self.s.mmu.work_ram[addr - 0xe000] = value, // echo RAM
             0xfe00..=0xfe9f => self.s.mmu.oam[addr - 0xfe00] = value,
-            0xff00 => self.s.joypad.write_p1(value),
-            0xff01..=0xff02 => (), // serial registers
-            0xff04 => self.s.timer.write_div(value),
-            0xff05 => self.s.timer.write_tima(value),
-            0xff06 => self.s.timer.write_tma(value),
-            0xff07 => self.s.timer.write_tac(value),
-            0xff0f => self.s.cpu.interrupts.write_flag(value),
-            0xff10..=0xff3f => (), // audio registers
-            0xff40 => self.s.ppu.write_lcdc(value),
-            0xff41 => self.s.ppu.write_stat(value),
-            0xff42 => self.s.ppu.write_scy(value),
-            0xff43 => self.s.ppu.write_scx(value),
-            0xff45 => self.s.ppu.write_lyc(value),
-            0xff46 => self.s.dma.write(value),
-            0xff47 => self.s.ppu.write_bgp(value),
-            0xff48 => self.s.ppu.write_obp0(value),
-            0xff49 => self.s.ppu.write_obp1(value),
-            0xff4a => self.s.ppu.write_wy(value),
-            0xff4b => self.s.ppu.write_wx(value),
-            0xff50 => (), // boot ROM enable
+            0xfea0..=0xfeff => (), // unusable
+            0xff00..=0xff7f => io::write(self.s, addr, value),
             0xff80..=0xfffe => self.s.mmu.high_ram[addr - 0xff80] = value,
             0xffff => self.s.cpu.interrupts.write_enable(value),
-            _ => warn!("unknown write address: {addr:#x}"),
         };
     }
+
+    fn ppu_blocks(&self, addr: u16) -> bool {
+        match addr {
+            0x8000..=0x9fff => self.s.ppu.vram_blocked(),
+            0xfe00..=0xfe9f => self.s.ppu.oam_blocked(),
+            _ => false,
+        }
+    }
+}
+
+/// A memory region that can be accessed by bank, independent of the current bank mapping.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    Rom,
+    CartRam,
+    Vram,
+    Wram,
+    Oam,
+    Hram,
+}
+
+/// Read the value at `addr` from `state`, without any side effects.
+///
+/// Unlike CPU reads, this sees VRAM and OAM even while the PPU blocks them.
+pub(crate) fn peek(state: &State, addr: u16) -> u8 {
+    let value = match state.mmu.locate(addr) {
+        Some((region, bank, offset)) => state.mmu.peek_banked(region, bank, offset),
+        None => match addr {
+            0xfea0..=0xfeff => Some(0x00),
+            0xff00..=0xff7f => Some(io::read(state, addr)),
+            0xffff => Some(state.cpu.interrupts.read_enable()),
+            _ => None,
+        },
+    };
+    value.unwrap_or(0xff)
+}
+
+/// Return the memory region that `addr` maps into, if any.
+pub(crate) fn region_at(addr: u16) -> Option<Region> {
+    map_addr(addr).map(|(region, _)| region)
+}
+
+/// Return the memory region that `addr` maps into, together with the address that the start of
+/// the mapped bank appears at.
+fn map_addr(addr: u16) -> Option<(Region, u16)> {
+    let mapping = match addr {
+        0x0000..=0x3fff => (Region::Rom, 0x0000),
+        0x4000..=0x7fff => (Region::Rom, 0x4000),
+        0x8000..=0x9fff => (Region::Vram, 0x8000),
+        0xa000..=0xbfff => (Region::CartRam, 0xa000),
+        0xc000..=0xdfff => (Region::Wram, 0xc000),
+        0xe000..=0xfdff => (Region::Wram, 0xe000), // echo RAM
+        0xfe00..=0xfe9f => (Region::Oam, 0xfe00),
+        0xff80..=0xfffe => (Region::Hram, 0xff80),
+        _ => return None,
+    };
+    Some(mapping)
 }
 
 #[derive(Debug)]
@@ -161,6 +235,60 @@ impl MmuState {
         }
     }
 
+    /// Return the memory region, bank and offset mapped at `addr`, if `addr` maps to memory.
+    pub fn locate(&self, addr: u16) -> Option<(Region, u16, u16)> {
+        let (region, start) = map_addr(addr)?;
+        let bank = match region {
+            Region::Rom if addr >= 0x4000 => self.mapper.rom_bank(),
+            Region::CartRam => self.mapper.ram_bank(),
+            _ => 0,
+        };
+        Some((region, bank, addr - start))
+    }
+
+    /// Return the number of the memory bank mapped at `addr`.
+    pub fn bank_at(&self, addr: u16) -> u16 {
+        self.locate(addr).map_or(0, |(_, bank, _)| bank)
+    }
+
+    pub fn banks(&self, region: Region) -> u16 {
+        match region {
+            Region::Rom => self.mapper.rom_banks(),
+            Region::CartRam => self.mapper.ram_banks(),
+            Region::Vram | Region::Wram | Region::Oam | Region::Hram => 1,
+        }
+    }
+
+    pub fn peek_banked(&self, region: Region, bank: u16, offset: u16) -> Option<u8> {
+        match region {
+            Region::Rom => self.mapper.peek_rom(bank, offset),
+            Region::CartRam => self.mapper.peek_ram(bank, offset),
+            _ if bank != 0 => None,
+            Region::Vram => self.video_ram.get(offset),
+            Region::Wram => self.work_ram.get(offset),
+            Region::Oam => self.oam.get(offset),
+            Region::Hram => self.high_ram.get(offset),
+        }
+    }
+
+    /// Write a byte into a memory region, returning whether the location exists.
+    pub fn poke_banked(&mut self, region: Region, bank: u16, offset: u16, value: u8) -> bool {
+        let slot = match region {
+            Region::Rom => return self.mapper.poke_rom(bank, offset, value),
+            Region::CartRam => return self.mapper.poke_ram(bank, offset, value),
+            _ if bank != 0 => None,
+            Region::Vram => self.video_ram.get_mut(offset),
+            Region::Wram => self.work_ram.get_mut(offset),
+            Region::Oam => self.oam.get_mut(offset),
+            Region::Hram => self.high_ram.get_mut(offset),
+        };
+        slot.map(|v| *v = value).is_some()
+    }
+
+    pub fn take_ram_dirty(&mut self) -> bool {
+        self.mapper.take_ram_dirty()
+    }
+
     pub fn dump_ram<W: Write>(&self, w: W) -> Result<()> {
         self.mapper.dump_ram(w)
     }
@@ -0,0 +1,64 @@
+use super::{region_at, Mmu, Region};
+use crate::bits::BitsExt;
+use crate::dma::Dma;
+use crate::ppu::Ppu;
+use crate::testing;
+use crate::timer::Timer;
+
+fn io_registers(mmu: &Mmu) -> Vec<u8> {
+    (0xff00..=0xff7f)
+        .chain([0xffff])
+        .map(|a| mmu.peek(a))
+        .collect()
+}
+
+#[test]
+fn poke_has_no_side_effects() {
+    let mut s = testing::state(&[]);
+
+    // Stop in HBlank, where a STAT write would raise an interrupt.
+    s.ppu.write_lcdc(0x91);
+    (0..760).for_each(|_| Ppu::new(&mut s).step());
+    assert_eq!(s.ppu.read_stat() & 0x03, 0);
+
+    // Overflow TIMA, so it's reloaded in the next cycle. Afterwards, the bit of the counter
+    // selected by TAC is high, so resetting the divider would tick TIMA.
+    let mut mmu = Mmu::new(&mut s);
+    mmu.poke(0xff04, 0x02);
+    mmu.poke(0xff05, 0xff);
+    mmu.poke(0xff06, 0x42);
+    mmu.write(0xff07, 0b101);
+    (0..4).for_each(|_| Timer::new(&mut s).step());
+    s.timer.poke_tac(0b100);
+
+    // Start a DMA transfer.
+    Mmu::new(&mut s).write(0xff46, 0xc0);
+    (0..3).for_each(|_| Dma::new(&mut s).step());
+    let dma = format!("{:?}", s.dma);
+
+    s.cpu.interrupts.write_flag(0);
+    let mut mmu = Mmu::new(&mut s);
+    let regs = io_registers(&mmu);
+    for (addr, value) in (0xff00..=0xff7f).chain([0xffff]).zip(regs.clone()) {
+        mmu.poke(addr, value);
+    }
+    assert_eq!(io_registers(&mmu), regs);
+    assert_eq!(format!("{:?}", s.dma), dma);
+
+    // The reload still happens and the STAT interrupt isn't raised.
+    Timer::new(&mut s).step();
+    Ppu::new(&mut s).step();
+    assert_eq!(s.timer.read_tima(), 0x42);
+    assert!(s.cpu.interrupts.read_flag().bit(2));
+    assert!(!s.cpu.interrupts.read_flag().bit(1));
+}
+
+#[test]
+fn echo_ram_maps_to_work_ram() {
+    let mut s = testing::state(&[]);
+    Mmu::new(&mut s).write(0xc123, 0x42);
+
+    assert_eq!(s.mmu.locate(0xe123), Some((Region::Wram, 0, 0x123)));
+    assert_eq!(region_at(0xfdff), Some(Region::Wram));
+    assert_eq!(super::peek(&s, 0xe123), 0x42);
+}
@@ -18,6 +18,8 @@ impl<'a> Fetcher<'a> {
     }
 
     pub(super) fn step(&mut self) -> Option<Color> {
+        self.suspend_for_object();
+
         self.s.ppu.fetch.step = match mem::take(&mut self.s.ppu.fetch.step) {
             Step::Bg(step) => BgFetcher::new(self.s).step(step),
             Step::Obj(step) => ObjFetcher::new(self.s).step(step),
@@ -49,6 +51,30 @@ impl<'a> Fetcher<'a> {
         }
     }
 
+    /// Switch to fetching the next pending object, once the background fetcher has gotten far
+    /// enough in its current fetch.
+    ///
+    /// The background fetch is suspended before reading the high tile row byte, which leads to
+    /// the documented object penalties of 6 to 11 dots. At the start of the line, the first tile
+    /// is pushed before any objects are fetched.
+    fn suspend_for_object(&mut self) {
+        let fetch = &mut self.s.ppu.fetch;
+        if fetch.pending_objects.is_empty() || fetch.bg_fifo.is_empty() {
+            return;
+        }
+
+        let step = match mem::take(&mut fetch.step) {
+            Step::Bg(step @ (BgStep::FetchTileRowHigh { .. } | BgStep::PushPixels { .. })) => step,
+            step => {
+                fetch.step = step;
+                return;
+            }
+        };
+        let slot = fetch.pending_objects.pop().unwrap();
+        fetch.suspended = Some(step);
+        fetch.step = Step::Obj(ObjStep::FetchFlags { slot });
+    }
+
     fn waiting_for_objects(&self) -> bool {
         let fetch = &self.s.ppu.fetch;
         !fetch.pending_objects.is_empty() || matches!(fetch.step, Step::Obj(_))
@@ -57,7 +83,10 @@ impl<'a> Fetcher<'a> {
     fn find_hit_objects(&self) -> Vec<object::Slot> {
         let x = self.s.ppu.fetch.obj_x;
         let slots = &self.s.ppu.object_slots;
-        slots.iter().filter(|obj| obj.x == x).copied().collect()
+        // Objects that are partly hidden behind the left edge are fetched once the first visible
+        // pixel is reached.
+        let hit = |obj: &&object::Slot| obj.x.max(8) == x;
+        slots.iter().filter(hit).copied().collect()
     }
 
     fn pop_pixel(&mut self) -> Option<Color> {
@@ -91,7 +120,24 @@ impl<'a> Fetcher<'a> {
         }
 
         let r = &self.s.ppu.registers;
-        let obj_wins = match (bg_px, obj_px.color, r.lcdc.bg_enable()) {
+        let layers = self.s.ppu.layers;
+
+        if layers.object_boxes && self.on_object_box() {
+            return Color::Highlight;
+        }
+
+        let bg_visible = match self.s.ppu.inside_window {
+            false => layers.background,
+            true => layers.window,
+        };
+        let bg_px = if bg_visible { bg_px } else { ColorIdx::C0 };
+        let obj_color = if layers.objects {
+            obj_px.color
+        } else {
+            ColorIdx::C0
+        };
+
+        let obj_wins = match (bg_px, obj_color, r.lcdc.bg_enable()) {
             (_, ColorIdx::C0, _) => false,
             (_, _, false) => true,
             (ColorIdx::C0, _, true) => true,
@@ -101,12 +147,29 @@ impl<'a> Fetcher<'a> {
         if obj_wins {
             let obp = if obj_px.palette1 { r.obp1 } else { r.obp0 };
             translate_color(obp, obj_px.color)
-        } else if r.lcdc.bg_enable() {
+        } else if r.lcdc.bg_enable() && bg_visible {
             translate_color(r.bgp, bg_px)
         } else {
             Color::White
         }
     }
+
+    /// Whether the current pixel lies on the outline of an object on this line.
+    fn on_object_box(&self) -> bool {
+        let r = &self.s.ppu.registers;
+        let height = if r.lcdc.obj_size() { 16 } else { 8 };
+        let x = i16::from(self.s.ppu.draw_x);
+        let y = i16::from(r.ly);
+
+        self.s.ppu.object_slots.iter().any(|obj| {
+            let left = i16::from(obj.x) - 8;
+            let top = i16::from(obj.y) - 16;
+            let (right, bottom) = (left + 7, top + height - 1);
+
+            let inside = (left..=right).contains(&x) && (top..=bottom).contains(&y);
+            inside && (x == left || x == right || y == top || y == bottom)
+        })
+    }
 }
 
 #[derive(Debug, Default)]
@@ -118,6 +181,13 @@ pub(super) struct Fetch {
     obj_fifo: Fifo<ObjPixel>,
     pending_objects: Vec<object::Slot>,
     step: Step,
+    /// The background fetch interrupted by an object fetch.
+    #[serde(default)]
+    suspended: Option<BgStep>,
+    /// Whether the fetcher is performing the first tile fetch of the line, whose result is
+    /// discarded.
+    #[serde(default)]
+    warmup: bool,
 }
 
 impl Fetch {
@@ -125,14 +195,20 @@ impl Fetch {
         Self {
             bg_fifo: Fifo::with_discard(scx & 7),
             obj_fifo: Fifo::with_discard(7),
+            warmup: true,
             ..Default::default()
         }
     }
 
+    pub(super) fn has_pixels(&self) -> bool {
+        !self.bg_fifo.is_empty()
+    }
+
     pub(super) fn enable_window(&mut self) {
         self.bg_x = 0;
         self.bg_fifo = Default::default();
         self.step = Default::default();
+        self.suspended = None;
     }
 }
 
@@ -231,7 +307,11 @@ impl<'a> BgFetcher<'a> {
             }
             FetchTileRowHigh { addr, low } => {
                 let high = Mmu::new(self.s).read(addr);
-                PushPixels { low, high }
+                if mem::take(&mut self.s.ppu.fetch.warmup) {
+                    LocateTileId
+                } else {
+                    PushPixels { low, high }
+                }
             }
             PushPixels { low, high } => {
                 return self.push_pixels(low, high);
@@ -281,9 +361,6 @@ impl<'a> BgFetcher<'a> {
         let fetch = &mut self.s.ppu.fetch;
 
         if !fetch.bg_fifo.is_empty() {
-            if let Some(slot) = fetch.pending_objects.pop() {
-                return Step::Obj(ObjStep::FetchFlags { slot });
-            }
             return Step::Bg(BgStep::PushPixels { low, high });
         }
 
@@ -321,11 +398,6 @@ enum ObjStep {
         obj: object::Attributes,
         low: u8,
     },
-    PushPixels {
-        obj: object::Attributes,
-        low: u8,
-        high: u8,
-    },
 }
 
 struct ObjFetcher<'a> {
@@ -350,7 +422,7 @@ impl<'a> ObjFetcher<'a> {
                 LocateTileRowLow {
                     obj: object::Attributes {
                         y: slot.y,
-                        _x: slot.x,
+                        x: slot.x,
                         tile_id,
                         flags,
                     },
@@ -370,11 +442,9 @@ impl<'a> ObjFetcher<'a> {
             }
             FetchTileRowHigh { addr, obj, low } => {
                 let high = self.fetch_tile_row(addr, &obj);
-                PushPixels { obj, low, high }
-            }
-            PushPixels { obj, low, high } => {
                 self.push_pixels(obj, low, high);
-                return Step::Bg(Default::default());
+                let bg_step = self.s.ppu.fetch.suspended.take();
+                return Step::Bg(bg_step.unwrap_or_default());
             }
         };
         Step::Obj(next_step)
@@ -419,8 +489,9 @@ impl<'a> ObjFetcher<'a> {
     }
 
     fn push_pixels(&mut self, obj: object::Attributes, low: u8, high: u8) {
-        let colors = merge_tile_row(low, high);
-        for (px, new) in self.s.ppu.fetch.obj_fifo.iter_mut().zip(colors.into_iter()) {
+        let hidden = 8_u8.saturating_sub(obj.x);
+        let colors = merge_tile_row(low, high).into_iter().skip(hidden.into());
+        for (px, new) in self.s.ppu.fetch.obj_fifo.iter_mut().zip(colors) {
             if px.color == ColorIdx::C0 {
                 *px = ObjPixel {
                     color: new,
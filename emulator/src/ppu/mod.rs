@@ -8,12 +8,18 @@ mod fetcher;
 mod fifo;
 mod object;
 mod state;
+#[cfg(test)]
+mod tests;
+mod viewer;
 
 use self::fetcher::{Fetch, Fetcher};
 
+pub use self::object::{Attributes, Flags};
+pub use self::state::Layers;
 pub(crate) use self::state::PpuState;
+pub(crate) use self::viewer::{objects, render_tile_map, render_tiles};
+pub use self::viewer::{Object, TileMap};
 
-const FRAME_LINES: u32 = 154;
 const LINE_DOTS: u16 = 456;
 
 pub(crate) struct Ppu<'a> {
@@ -30,8 +36,15 @@ impl<'a> Ppu<'a> {
     }
 
     pub fn step(&mut self) {
+        self.update_lcd_enable();
+        if self.s.cpu.stopped() || self.s.ppu.mode == Mode::PpuOff {
+            self.step_blank();
+            return;
+        }
+
         match self.s.ppu.mode {
             Mode::OamScan => self.step_oam_scan(),
+            Mode::LcdStart => self.step_lcd_start(),
             Mode::Draw => self.step_draw(),
             Mode::HBlank | Mode::VBlank | Mode::PpuOff => (),
         }
@@ -40,9 +53,58 @@ impl<'a> Ppu<'a> {
         self.end_dot();
     }
 
+    /// While the LCD is off or halted in STOP mode, the PPU only produces blank frames to keep the
+    /// frontend running.
+    fn step_blank(&mut self) {
+        self.s.ppu.blank_dots += 1;
+        if self.s.ppu.blank_dots == Frame::DOTS {
+            self.s.ppu.blank_dots = 0;
+            self.ready_frame = Some(Frame::default());
+        }
+    }
+
+    fn update_lcd_enable(&mut self) {
+        let enabled = self.s.ppu.registers.lcdc.ppu_enable();
+        let off = self.s.ppu.mode == Mode::PpuOff;
+        if enabled && off {
+            self.enable_lcd();
+        } else if !enabled && !off {
+            self.disable_lcd();
+        }
+    }
+
+    /// Turning on the LCD starts a new frame that isn't shown. Its first line is shortened and
+    /// reports mode 0 instead of mode 2.
+    fn enable_lcd(&mut self) {
+        self.set_mode(Mode::LcdStart);
+        self.s.ppu.object_slots = Default::default();
+        self.s.ppu.line_dots = 4;
+        self.s.ppu.hide_frame = true;
+        self.s.ppu.stat_written = false;
+    }
+
+    /// Turning off the LCD stops the PPU immediately, resetting LY and the mode.
+    fn disable_lcd(&mut self) {
+        self.set_mode(Mode::PpuOff);
+        let ppu = &mut self.s.ppu;
+        ppu.registers.ly = 0;
+        ppu.line_dots = 0;
+        ppu.window_y = 0;
+        ppu.inside_window = false;
+        ppu.frame = Default::default();
+        ppu.stat_interrupt = false;
+        ppu.blank_dots = 0;
+    }
+
     fn end_dot(&mut self) {
         self.s.ppu.line_dots += 1;
 
+        // During the last line of the frame, LY only reads 153 for a single M-cycle.
+        let regs = &mut self.s.ppu.registers;
+        if self.s.ppu.mode == Mode::VBlank && regs.ly == 153 && self.s.ppu.line_dots == 4 {
+            regs.ly = 0;
+        }
+
         if self.s.ppu.line_dots == LINE_DOTS {
             self.end_line();
         }
@@ -50,34 +112,37 @@ impl<'a> Ppu<'a> {
 
     fn end_line(&mut self) {
         self.s.ppu.line_dots = 0;
-        self.s.ppu.registers.ly += 1;
         if self.s.ppu.inside_window {
             self.s.ppu.window_y += 1;
             self.s.ppu.inside_window = false;
         }
 
+        if self.s.ppu.mode == Mode::VBlank && self.s.ppu.registers.ly == 0 {
+            self.end_frame();
+            return;
+        }
+
+        self.s.ppu.registers.ly += 1;
         let y = u32::from(self.s.ppu.registers.ly);
         let mode = self.s.ppu.mode;
         if y < Frame::HEIGHT && mode == Mode::HBlank {
             self.enter_oam_scan();
         } else if y == Frame::HEIGHT && mode == Mode::HBlank {
             self.enter_vblank();
-        } else if y == FRAME_LINES {
-            self.end_frame();
         }
     }
 
     fn end_frame(&mut self) {
         let frame = mem::take(&mut self.s.ppu.frame);
-        self.ready_frame = Some(frame);
+        if mem::take(&mut self.s.ppu.hide_frame) {
+            self.ready_frame = Some(Frame::default());
+        } else {
+            self.ready_frame = Some(frame);
+        }
 
         self.s.ppu.registers.ly = 0;
         self.s.ppu.window_y = 0;
-        if self.s.ppu.registers.lcdc.ppu_enable() {
-            self.enter_oam_scan();
-        } else {
-            self.set_mode(Mode::PpuOff);
-        };
+        self.enter_oam_scan();
     }
 
     pub fn take_frame(&mut self) -> Option<Frame> {
@@ -117,6 +182,13 @@ impl<'a> Ppu<'a> {
         }
     }
 
+    /// The first line after turning on the LCD doesn't scan OAM, so no objects are drawn on it.
+    fn step_lcd_start(&mut self) {
+        if self.s.ppu.line_dots == 79 {
+            self.enter_draw();
+        }
+    }
+
     fn step_draw(&mut self) {
         self.maybe_enable_window();
 
@@ -135,6 +207,12 @@ impl<'a> Ppu<'a> {
             return;
         }
 
+        // The window only starts once the background fetcher has produced pixels, so it always
+        // discards a tile fetch.
+        if !self.s.ppu.fetch.has_pixels() {
+            return;
+        }
+
         let r = &self.s.ppu.registers;
         let wy_triggered = r.wy <= r.ly;
         let wx_triggered = r.wx <= self.s.ppu.draw_x + 7;
@@ -149,8 +227,12 @@ impl<'a> Ppu<'a> {
         let regs = &mut self.s.ppu.registers;
         regs.refresh_stat_lycly();
 
+        let written = mem::take(&mut self.s.ppu.stat_written);
         let prev_high = self.s.ppu.stat_interrupt;
-        let new_high = regs.mode_interrupt() || regs.lyc_interrupt();
+        let new_high = regs.mode_interrupt()
+            || regs.lyc_interrupt()
+            || regs.vblank_oam_interrupt(self.s.ppu.line_dots)
+            || written && regs.stat_write_interrupt();
         if !prev_high && new_high {
             self.s.cpu.interrupts.set_flag(Interrupt::LcdStat);
         }
@@ -167,12 +249,14 @@ enum Mode {
     Draw,
     HBlank,
     VBlank,
+    /// The start of the first line after turning on the LCD, in place of the OAM scan.
+    LcdStart,
 }
 
 impl Mode {
     fn stat(&self) -> u8 {
         match self {
-            Mode::PpuOff | Mode::HBlank => 0,
+            Mode::PpuOff | Mode::HBlank | Mode::LcdStart => 0,
             Mode::VBlank => 1,
             Mode::OamScan => 2,
             Mode::Draw => 3,
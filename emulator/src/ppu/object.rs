@@ -1,5 +1,5 @@
 use crate::bits::BitsExt;
-use crate::mmu::Mmu;
+use crate::mmu::{Mmu, Region};
 use crate::state::State;
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -12,16 +12,16 @@ pub(super) struct Slot {
 
 #[derive(Clone, Debug, Default)]
 #[derive(serde::Serialize, serde::Deserialize)]
-pub(super) struct Attributes {
-    pub(super) y: u8,
-    pub(super) _x: u8,
-    pub(super) tile_id: u8,
-    pub(super) flags: Flags,
+pub struct Attributes {
+    pub y: u8,
+    pub x: u8,
+    pub tile_id: u8,
+    pub flags: Flags,
 }
 
 #[derive(Clone, Copy, Debug, Default)]
 #[derive(serde::Serialize, serde::Deserialize)]
-pub(super) struct Flags(u8);
+pub struct Flags(u8);
 
 impl Flags {
     pub fn palette(&self) -> bool {
@@ -110,6 +110,19 @@ pub(super) fn read_tile_id(state: &mut State, index: u16) -> u8 {
     Mmu::new(state).read(addr)
 }
 
+/// Read the attributes of the object at `index`, without any side effects.
+pub(super) fn peek_attributes(state: &State, index: u16) -> Attributes {
+    let mmu = &state.mmu;
+    let offset = index * 4;
+    let peek = |i| mmu.peek_banked(Region::Oam, 0, offset + i).unwrap_or(0xff);
+    Attributes {
+        y: peek(0),
+        x: peek(1),
+        tile_id: peek(2),
+        flags: Flags(peek(3)),
+    }
+}
+
 pub(super) fn read_flags(state: &mut State, index: u16) -> Flags {
     let addr = object_address(index) + 3;
     Flags(Mmu::new(state).read(addr))
@@ -18,9 +18,38 @@ pub(crate) struct PpuState {
     pub(super) oam_scan: object::Scan,
     pub(super) fetch: Fetch,
     pub(super) stat_interrupt: bool,
+    /// Dots elapsed since the last blank frame while the LCD is off or the CPU is in STOP mode.
+    #[serde(default)]
+    pub(super) blank_dots: u32,
+    /// Whether the current frame is the first one after turning on the LCD, which isn't shown.
+    #[serde(default)]
+    pub(super) hide_frame: bool,
+    /// Whether the CPU wrote STAT since the last dot.
+    #[serde(default)]
+    pub(super) stat_written: bool,
+    #[serde(skip)]
+    pub(super) layers: Layers,
 }
 
 impl PpuState {
+    pub fn layers(&self) -> Layers {
+        self.layers
+    }
+
+    pub fn set_layers(&mut self, layers: Layers) {
+        self.layers = layers;
+    }
+
+    /// Whether the PPU is currently scanning or reading OAM.
+    pub fn oam_blocked(&self) -> bool {
+        matches!(self.mode, Mode::OamScan | Mode::Draw)
+    }
+
+    /// Whether the PPU is currently reading VRAM.
+    pub fn vram_blocked(&self) -> bool {
+        self.mode == Mode::Draw
+    }
+
     pub fn read_lcdc(&self) -> u8 {
         self.registers.lcdc.0
     }
@@ -36,6 +65,13 @@ impl PpuState {
     pub fn write_stat(&mut self, value: u8) {
         let value = value & 0x78 | self.registers.stat & 0x07;
         self.registers.stat = value;
+        self.stat_written = true;
+    }
+
+    /// Set the STAT register without the write glitch triggering an interrupt.
+    pub fn poke_stat(&mut self, value: u8) {
+        let value = value & 0x78 | self.registers.stat & 0x07;
+        self.registers.stat = value;
     }
 
     pub fn read_scy(&self) -> u8 {
@@ -107,6 +143,29 @@ impl PpuState {
     }
 }
 
+/// Switches for the layers the PPU draws, for debugging.
+///
+/// Disabled layers are drawn as if they were transparent, without affecting the PPU's timing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layers {
+    pub background: bool,
+    pub window: bool,
+    pub objects: bool,
+    /// Outline the bounding boxes of the objects on each line.
+    pub object_boxes: bool,
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self {
+            background: true,
+            window: true,
+            objects: true,
+            object_boxes: false,
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 #[derive(serde::Serialize, serde::Deserialize)]
 pub(super) struct Registers {
@@ -146,6 +205,19 @@ impl Registers {
         let enabled = self.stat.bit(6);
         lycly && enabled
     }
+
+    /// At the start of line 144, the mode 2 interrupt source is triggered alongside the VBlank
+    /// one.
+    pub(super) fn vblank_oam_interrupt(&self, line_dots: u16) -> bool {
+        self.ly == 144 && line_dots == 0 && self.stat.bit(5)
+    }
+
+    /// On the DMG, writing STAT enables all interrupt sources for a moment, so the write triggers
+    /// an interrupt during HBlank, VBlank or when LY=LYC.
+    pub(super) fn stat_write_interrupt(&self) -> bool {
+        let mode = self.stat & 0x03;
+        mode == 0 || mode == 1 || self.stat.bit(2)
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
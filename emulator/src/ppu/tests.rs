@@ -0,0 +1,268 @@
+use super::{Mode, Ppu};
+use crate::bits::BitsExt;
+use crate::mmu::Mmu;
+use crate::state::State;
+use crate::testing;
+
+/// A state with the LCD, background and objects enabled.
+fn state() -> State {
+    let mut s = testing::state(&[]);
+    s.ppu.write_lcdc(0x93);
+    s
+}
+
+fn step(s: &mut State) {
+    Ppu::new(s).step();
+}
+
+/// Run until line `ly` starts, once the PPU reached the VBlank of the hidden first frame.
+fn run_to_line(s: &mut State, ly: u8) {
+    while s.ppu.mode != Mode::VBlank {
+        step(s);
+    }
+    while s.ppu.registers.ly != ly || s.ppu.line_dots != 0 {
+        step(s);
+    }
+}
+
+/// Place 8x8 objects at the given OAM X positions on line 0.
+fn place_objects(s: &mut State, xs: &[u8]) {
+    let mut mmu = Mmu::new(s);
+    for (i, x) in xs.iter().enumerate() {
+        let addr = 0xfe00 + 4 * i as u16;
+        mmu.poke(addr, 16);
+        mmu.poke(addr + 1, *x);
+    }
+}
+
+/// Run through line 0 and return how many dots mode 3 took.
+fn draw_dots(s: &mut State) -> u16 {
+    run_to_line(s, 0);
+    let mut dots = 0;
+    while s.ppu.mode != Mode::HBlank {
+        step(s);
+        if s.ppu.mode == Mode::Draw {
+            dots += 1;
+        }
+    }
+    dots
+}
+
+#[test]
+fn draw_takes_172_dots() {
+    let mut s = state();
+    assert_eq!(draw_dots(&mut s), 172);
+}
+
+#[test]
+fn scx_extends_draw() {
+    for scx in 0..16 {
+        let mut s = state();
+        s.ppu.write_scx(scx);
+        assert_eq!(draw_dots(&mut s), 172 + u16::from(scx % 8), "scx={scx}");
+    }
+}
+
+#[test]
+fn object_penalty_depends_on_tile_offset() {
+    // The penalty is 6 dots plus the rest of the background fetch, which depends on the object's
+    // position within its background tile.
+    let cases = [
+        (8, 11),
+        (9, 10),
+        (12, 7),
+        (13, 6),
+        (15, 6),
+        (16, 11),
+        (100, 7),
+    ];
+    for (x, penalty) in cases {
+        let mut s = state();
+        place_objects(&mut s, &[x]);
+        assert_eq!(draw_dots(&mut s), 172 + penalty, "x={x}");
+    }
+}
+
+#[test]
+fn objects_behind_left_edge_are_fetched_at_first_pixel() {
+    for x in 0..8 {
+        let mut s = state();
+        place_objects(&mut s, &[x]);
+        assert_eq!(draw_dots(&mut s), 172 + 11, "x={x}");
+    }
+}
+
+#[test]
+fn objects_in_same_tile_share_fetch_wait() {
+    let mut s = state();
+    place_objects(&mut s, &[8, 9]);
+    assert_eq!(draw_dots(&mut s), 172 + 11 + 6);
+
+    let mut s = state();
+    place_objects(&mut s, &[0, 8]);
+    assert_eq!(draw_dots(&mut s), 172 + 11 + 6);
+}
+
+#[test]
+fn objects_offscreen_right_are_free() {
+    let mut s = state();
+    place_objects(&mut s, &[168]);
+    assert_eq!(draw_dots(&mut s), 172);
+}
+
+#[test]
+fn ly_reads_0_early_on_line_153() {
+    let mut s = state();
+    run_to_line(&mut s, 0);
+    while s.ppu.registers.ly != 153 {
+        step(&mut s);
+    }
+    assert_eq!(s.ppu.line_dots, 0);
+
+    for _ in 0..4 {
+        assert_eq!(s.ppu.read_ly(), 153);
+        step(&mut s);
+    }
+    assert_eq!(s.ppu.read_ly(), 0);
+    assert_eq!(s.ppu.mode, Mode::VBlank);
+
+    while s.ppu.mode == Mode::VBlank {
+        step(&mut s);
+    }
+    assert_eq!(s.ppu.read_ly(), 0);
+    assert_eq!(s.ppu.line_dots, 0);
+}
+
+#[test]
+fn first_line_after_lcd_enable_leaves_oam_accessible() {
+    let mut s = testing::state(&[]);
+    Mmu::new(&mut s).poke(0xfe00, 0x42);
+    s.ppu.write_lcdc(0x93);
+
+    step(&mut s);
+    assert_eq!(s.ppu.read_stat() & 0x03, 0);
+    assert_eq!(Mmu::new(&mut s).cpu_read(0xfe00), 0x42);
+    while s.ppu.mode != Mode::Draw {
+        step(&mut s);
+    }
+    assert_eq!(s.ppu.line_dots, 80);
+    assert_eq!(Mmu::new(&mut s).cpu_read(0xfe00), 0xff);
+
+    run_to_line(&mut s, 1);
+    step(&mut s);
+    assert_eq!(s.ppu.read_stat() & 0x03, 2);
+    assert_eq!(Mmu::new(&mut s).cpu_read(0xfe00), 0xff);
+}
+
+#[test]
+fn draw_blocks_cpu_vram_access() {
+    let mut emu = testing::emulator(&[]);
+    let s = &mut emu.state;
+    Mmu::new(s).poke(0x8000, 0x42);
+    s.ppu.write_lcdc(0x93);
+    run_to_line(s, 0);
+    while s.ppu.mode != Mode::Draw {
+        step(s);
+    }
+
+    let mut mmu = Mmu::new(s);
+    assert_eq!(mmu.cpu_read(0x8000), 0xff);
+    mmu.cpu_write(0x8000, 0x24);
+
+    // The debugger still sees the actual contents.
+    assert_eq!(emu.peek(0x8000), 0x42);
+}
+
+/// Step a single dot and return whether it raised the STAT interrupt.
+fn step_stat_irq(s: &mut State) -> bool {
+    s.cpu.interrupts.write_flag(0);
+    step(s);
+    s.cpu.interrupts.read_flag().bit(1)
+}
+
+fn write_stat_irqs(mode: Mode, lyc_match: bool) -> usize {
+    let mut s = state();
+    run_to_line(&mut s, 10);
+    if mode == Mode::VBlank {
+        run_to_line(&mut s, 150);
+    }
+    s.ppu.write_lyc(if lyc_match { 10 } else { 0 });
+    while s.ppu.mode != mode {
+        step(&mut s);
+    }
+
+    Mmu::new(&mut s).write(0xff41, 0x00);
+    (0..100).filter(|_| step_stat_irq(&mut s)).count()
+}
+
+#[test]
+fn stat_write_raises_interrupt_once() {
+    assert_eq!(write_stat_irqs(Mode::HBlank, false), 1);
+    assert_eq!(write_stat_irqs(Mode::VBlank, false), 1);
+    assert_eq!(write_stat_irqs(Mode::Draw, true), 1);
+    assert_eq!(write_stat_irqs(Mode::OamScan, true), 1);
+}
+
+#[test]
+fn stat_write_outside_sources_is_silent() {
+    assert_eq!(write_stat_irqs(Mode::Draw, false), 0);
+    assert_eq!(write_stat_irqs(Mode::OamScan, false), 0);
+}
+
+#[test]
+fn stat_poke_is_silent() {
+    let mut s = state();
+    run_to_line(&mut s, 10);
+    while s.ppu.mode != Mode::HBlank {
+        step(&mut s);
+    }
+
+    Mmu::new(&mut s).poke(0xff41, 0x40);
+    assert!(!step_stat_irq(&mut s));
+    assert_eq!(s.ppu.read_stat() & 0x78, 0x40);
+}
+
+#[test]
+fn mode_2_source_fires_at_start_of_line_144() {
+    let mut s = state();
+    run_to_line(&mut s, 144);
+    s.ppu.poke_stat(0x20);
+
+    let mut irqs = Vec::new();
+    while s.ppu.mode != Mode::OamScan {
+        let pos = (s.ppu.registers.ly, s.ppu.line_dots);
+        if step_stat_irq(&mut s) {
+            irqs.push(pos);
+        }
+    }
+    assert_eq!(irqs, [(144, 0)]);
+}
+
+#[test]
+fn no_interrupt_while_line_is_high() {
+    // The HBlank source keeps the line high into line 144, hiding the mode 2 source there.
+    let mut s = state();
+    run_to_line(&mut s, 143);
+    s.ppu.poke_stat(0x28);
+    while s.ppu.mode != Mode::HBlank {
+        step(&mut s);
+    }
+    step(&mut s);
+
+    let mut irqs = 0;
+    while s.ppu.registers.ly != 145 {
+        irqs += usize::from(step_stat_irq(&mut s));
+    }
+    assert_eq!(irqs, 0);
+
+    // Writing STAT while the line is high doesn't raise another interrupt either.
+    let mut s = state();
+    run_to_line(&mut s, 10);
+    s.ppu.poke_stat(0x08);
+    while s.ppu.mode != Mode::HBlank {
+        step(&mut s);
+    }
+    step(&mut s);
+    Mmu::new(&mut s).write(0xff41, 0x08);
+    assert!(!step_stat_irq(&mut s));
+}
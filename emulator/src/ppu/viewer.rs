@@ -0,0 +1,178 @@
+//! Rendering of the PPU's inputs, for debugging.
+//!
+//! All functions read VRAM and OAM without side effects, so they can be used at any time.
+
+use crate::bits::BitsExt;
+use crate::frame::{Color, Frame, Image};
+use crate::mmu::Region;
+use crate::state::State;
+
+use super::object::{self, Attributes};
+
+/// Number of tiles in VRAM.
+const TILE_COUNT: u32 = 384;
+/// Number of tiles per row in the tile data image.
+const TILES_PER_ROW: u32 = 16;
+/// Width and height of a tile map, in tiles.
+const MAP_TILES: u32 = 32;
+const OBJECT_COUNT: u16 = 40;
+
+const TRANSPARENT_RGBA: [u8; 4] = [0; 4];
+
+/// One of the two tile maps in VRAM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileMap {
+    /// The tile map at 0x9800.
+    Low,
+    /// The tile map at 0x9c00.
+    High,
+}
+
+/// An OAM entry, together with a rendering of its tiles.
+#[derive(Clone, Debug)]
+pub struct Object {
+    pub attributes: Attributes,
+    pub image: Image,
+}
+
+/// Render all tiles in VRAM, in a grid of 16 tiles per row, without applying a palette.
+pub(crate) fn render_tiles(state: &State) -> Image {
+    let rows = TILE_COUNT / TILES_PER_ROW;
+    let mut image = Image::new(TILES_PER_ROW * 8, rows * 8);
+
+    for tile in 0..TILE_COUNT {
+        let tile_x = (tile % TILES_PER_ROW) * 8;
+        let tile_y = (tile / TILES_PER_ROW) * 8;
+        let addr = 0x8000 + tile as u16 * 16;
+
+        for row in 0..8 {
+            let colors = read_tile_row(state, addr + row * 2);
+            for (x, idx) in colors.into_iter().enumerate() {
+                let rgba = SHADES[usize::from(idx)].rgba();
+                image.set_pixel(tile_x + x as u32, tile_y + u32::from(row), rgba);
+            }
+        }
+    }
+
+    image
+}
+
+/// Render a complete tile map with the current BG palette and tile data addressing, and outline
+/// the area visible through the SCX/SCY viewport.
+pub(crate) fn render_tile_map(state: &State, map: TileMap) -> Image {
+    let r = &state.ppu.registers;
+    let base = match map {
+        TileMap::Low => 0x9800,
+        TileMap::High => 0x9c00,
+    };
+
+    let mut image = Image::new(MAP_TILES * 8, MAP_TILES * 8);
+    for i in 0..MAP_TILES * MAP_TILES {
+        let id = peek_vram(state, base + i as u16);
+        let addr = bg_tile_addr(r.lcdc.bg_tile_data(), id);
+        let tile_x = (i % MAP_TILES) * 8;
+        let tile_y = (i / MAP_TILES) * 8;
+
+        for row in 0..8 {
+            let colors = read_tile_row(state, addr + row * 2);
+            for (x, idx) in colors.into_iter().enumerate() {
+                let rgba = shade(r.bgp, idx).rgba();
+                image.set_pixel(tile_x + x as u32, tile_y + u32::from(row), rgba);
+            }
+        }
+    }
+
+    draw_viewport(&mut image, u32::from(r.scx), u32::from(r.scy));
+    image
+}
+
+/// Decode all OAM entries and render their tiles with their palettes.
+///
+/// Transparent object pixels are rendered fully transparent.
+pub(crate) fn objects(state: &State) -> Vec<Object> {
+    let r = &state.ppu.registers;
+    let height = if r.lcdc.obj_size() { 16 } else { 8 };
+
+    (0..OBJECT_COUNT)
+        .map(|index| {
+            let attributes = object::peek_attributes(state, index);
+            let flags = attributes.flags;
+            let palette = if flags.palette() { r.obp1 } else { r.obp0 };
+            let tile_id = if height == 16 {
+                attributes.tile_id & 0xfe
+            } else {
+                attributes.tile_id
+            };
+
+            let mut image = Image::new(8, height);
+            for y in 0..height {
+                let row = if flags.y_flip() { height - 1 - y } else { y };
+                let addr = 0x8000 + u16::from(tile_id) * 16 + row as u16 * 2;
+                let mut colors = read_tile_row(state, addr);
+                if flags.x_flip() {
+                    colors.reverse();
+                }
+
+                for (x, idx) in colors.into_iter().enumerate() {
+                    let rgba = match idx {
+                        0 => TRANSPARENT_RGBA,
+                        _ => shade(palette, idx).rgba(),
+                    };
+                    image.set_pixel(x as u32, y, rgba);
+                }
+            }
+
+            Object { attributes, image }
+        })
+        .collect()
+}
+
+const SHADES: [Color; 4] = [Color::White, Color::Light, Color::Dark, Color::Black];
+
+fn shade(palette: u8, idx: u8) -> Color {
+    let bits = palette.bits(idx * 2..=idx * 2 + 1);
+    SHADES[usize::from(bits)]
+}
+
+fn peek_vram(state: &State, addr: u16) -> u8 {
+    state
+        .mmu
+        .peek_banked(Region::Vram, 0, addr - 0x8000)
+        .unwrap_or(0xff)
+}
+
+fn bg_tile_addr(unsigned: bool, id: u8) -> u16 {
+    match (unsigned, id.bit(7)) {
+        (false, false) => 0x9000 + u16::from(id) * 16,
+        (false, true) | (true, _) => 0x8000 + u16::from(id) * 16,
+    }
+}
+
+/// Read a row of tile data and return the color indexes of its pixels, from left to right.
+fn read_tile_row(state: &State, addr: u16) -> [u8; 8] {
+    let low = peek_vram(state, addr);
+    let high = peek_vram(state, addr + 1);
+
+    let mut colors = [0; 8];
+    for i in 0..8 {
+        colors[7 - usize::from(i)] = u8::from(high.bit(i)) << 1 | u8::from(low.bit(i));
+    }
+    colors
+}
+
+/// Outline the screen-sized area at the given position in a tile map image, wrapping around the
+/// edges like the PPU does.
+fn draw_viewport(image: &mut Image, scx: u32, scy: u32) {
+    let size = MAP_TILES * 8;
+    let right = scx + Frame::WIDTH - 1;
+    let bottom = scy + Frame::HEIGHT - 1;
+
+    for x in scx..=right {
+        image.set_pixel(x % size, scy % size, Color::Highlight.rgba());
+        image.set_pixel(x % size, bottom % size, Color::Highlight.rgba());
+    }
+    for y in scy..=bottom {
+        image.set_pixel(scx % size, y % size, Color::Highlight.rgba());
+        image.set_pixel(right % size, y % size, Color::Highlight.rgba());
+    }
+}
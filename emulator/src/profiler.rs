@@ -0,0 +1,228 @@
+//! A cycle-level profiler for the emulated program.
+//!
+//! The profiler runs at every instruction boundary and attributes the M-cycles since the previous
+//! boundary to the previous instruction. Calls, `rst`s and interrupt dispatches push a routine
+//! onto a shadow call stack, which is popped again once the stack pointer moves above the
+//! routine's entry SP.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use code::Inst;
+
+use crate::cpu::Register;
+use crate::debugger::{decode_at, Location};
+use crate::state::State;
+use crate::symbols::Symbols;
+
+/// Entry points of the interrupt handlers.
+const INTERRUPT_VECTORS: [u16; 5] = [0x40, 0x48, 0x50, 0x58, 0x60];
+/// Maximum number of hot spots included in the report.
+const MAX_HOT_SPOTS: usize = 50;
+
+/// The instruction that is currently executing.
+#[derive(Debug)]
+struct Current {
+    location: Location,
+    /// Address of the following instruction.
+    next_pc: u16,
+    sp: u16,
+    is_call: bool,
+    start_cycle: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Profiler {
+    current: Option<Current>,
+    /// The routines on the shadow call stack, innermost last.
+    stack: Vec<Location>,
+    /// The SP at the entry of each routine on the shadow call stack.
+    entry_sps: Vec<u16>,
+    profile: Profile,
+}
+
+impl Profiler {
+    /// Record that the CPU is about to execute the instruction at the current PC.
+    pub fn record(&mut self, state: &mut State) {
+        let pc = state.cpu.read_register(Register::PC);
+        let sp = state.cpu.read_register(Register::SP);
+        let location = Location {
+            bank: state.mmu.bank_at(pc),
+            addr: pc,
+        };
+
+        if let Some(prev) = self.current.take() {
+            self.attribute(&prev, state.cycles);
+
+            let entered = sp == prev.sp.wrapping_sub(2)
+                && pc != prev.next_pc
+                && (prev.is_call || INTERRUPT_VECTORS.contains(&pc));
+            if entered {
+                self.stack.push(location);
+                self.entry_sps.push(sp);
+            }
+        } else {
+            // The code running when profiling starts acts as the root routine.
+            self.stack.push(location);
+            self.entry_sps.push(sp);
+        }
+
+        while self.stack.len() > 1 && self.entry_sps.last().is_some_and(|s| sp > *s) {
+            self.stack.pop();
+            self.entry_sps.pop();
+        }
+
+        let (is_call, len) = match decode_at(state, pc) {
+            Some((Inst::Call(..) | Inst::Rst(_), len)) => (true, len),
+            Some((_, len)) => (false, len),
+            None => (false, 1),
+        };
+        self.current = Some(Current {
+            location,
+            next_pc: pc.wrapping_add(len),
+            sp,
+            is_call,
+            start_cycle: state.cycles,
+        });
+    }
+
+    /// Stop profiling and return the collected profile, with locations labeled by `symbols`.
+    pub fn finish(mut self, cycles: u64, symbols: &Symbols) -> Profile {
+        if let Some(current) = self.current.take() {
+            self.attribute(&current, cycles);
+        }
+
+        let p = &mut self.profile;
+        let locations = p.instructions.keys().chain(p.stacks.keys().flatten());
+        p.labels = locations
+            .filter_map(|l| symbols.label(*l).map(|label| (*l, label)))
+            .collect();
+
+        self.profile
+    }
+
+    fn attribute(&mut self, inst: &Current, cycles: u64) {
+        let count = cycles - inst.start_cycle;
+        let p = &mut self.profile;
+
+        p.total += count;
+        *p.instructions.entry(inst.location).or_default() += count;
+        *p.banks.entry(inst.location.bank).or_default() += count;
+
+        match p.stacks.get_mut(self.stack.as_slice()) {
+            Some(c) => *c += count,
+            None => {
+                p.stacks.insert(self.stack.clone(), count);
+            }
+        }
+    }
+}
+
+/// The M-cycle counts collected by the profiler.
+#[derive(Debug, Default)]
+pub struct Profile {
+    total: u64,
+    instructions: HashMap<Location, u64>,
+    banks: BTreeMap<u16, u64>,
+    /// Cycles spent in each call stack, innermost routine last.
+    stacks: HashMap<Vec<Location>, u64>,
+    labels: HashMap<Location, String>,
+}
+
+impl Profile {
+    /// Total number of profiled M-cycles.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Write a human-readable report of the routines, instructions and banks that took the most
+    /// cycles.
+    pub fn write_report<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let percent = |count: u64| count as f64 * 100. / self.total.max(1) as f64;
+
+        writeln!(w, "total: {} M-cycles", self.total)?;
+
+        writeln!(w, "\nroutines:")?;
+        writeln!(w, "        self       %        total       %  routine")?;
+        for (routine, (self_, total)) in sorted(self.routines(), |(s, _)| *s) {
+            writeln!(
+                w,
+                "{self_:>12} {:>6.2}% {total:>12} {:>6.2}%  {}",
+                percent(self_),
+                percent(total),
+                self.name(routine),
+            )?;
+        }
+
+        writeln!(w, "\nhot spots:")?;
+        let hot_spots = sorted(self.instructions.clone(), |c| *c);
+        for (location, count) in hot_spots.into_iter().take(MAX_HOT_SPOTS) {
+            let pct = percent(count);
+            writeln!(w, "{count:>12} {pct:>6.2}%  {}", self.name(location))?;
+        }
+
+        writeln!(w, "\nbanks:")?;
+        for (bank, count) in sorted(self.banks.clone(), |c| *c) {
+            writeln!(w, "{count:>12} {:>6.2}%  bank {bank:02x}", percent(count))?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the call stacks in the folded format understood by flamegraph tools.
+    pub fn write_folded<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let mut lines: Vec<_> = self
+            .stacks
+            .iter()
+            .map(|(stack, count)| {
+                let names: Vec<_> = stack.iter().map(|l| self.name(*l)).collect();
+                (names.join(";"), count)
+            })
+            .collect();
+        lines.sort();
+
+        for (stack, count) in lines {
+            writeln!(w, "{stack} {count}")?;
+        }
+        Ok(())
+    }
+
+    /// Return the label of the given location, or its bank and address if it has no label.
+    fn name(&self, location: Location) -> String {
+        match self.labels.get(&location) {
+            Some(label) => label.clone(),
+            None => location.to_string(),
+        }
+    }
+
+    /// Return the self and total cycles of every routine.
+    fn routines(&self) -> HashMap<Location, (u64, u64)> {
+        let mut routines: HashMap<Location, (u64, u64)> = HashMap::new();
+        for (stack, count) in &self.stacks {
+            let Some(innermost) = stack.last() else { continue };
+            routines.entry(*innermost).or_default().0 += count;
+
+            let mut seen = Vec::with_capacity(stack.len());
+            for routine in stack {
+                // Count recursive routines only once per stack.
+                if !seen.contains(routine) {
+                    seen.push(*routine);
+                    routines.entry(*routine).or_default().1 += count;
+                }
+            }
+        }
+        routines
+    }
+}
+
+/// Sort the given entries by descending count, then by key.
+fn sorted<K, V, I, F>(entries: I, count: F) -> Vec<(K, V)>
+where
+    K: Ord,
+    I: IntoIterator<Item = (K, V)>,
+    F: Fn(&V) -> u64,
+{
+    let mut entries: Vec<_> = entries.into_iter().collect();
+    entries.sort_by(|(ka, a), (kb, b)| count(b).cmp(&count(a)).then(ka.cmp(kb)));
+    entries
+}
@@ -5,7 +5,9 @@ use std::path::Path;
 use anyhow::{Context, Result};
 
 use crate::cpu::CpuState;
+use crate::debugger::DebugState;
 use crate::dma::DmaState;
+use crate::error::EmulatorError;
 use crate::joypad::JoypadState;
 use crate::mmu::{self, MmuState};
 use crate::ppu::PpuState;
@@ -22,14 +24,19 @@ pub(crate) struct State {
     pub cpu: CpuState,
     pub ppu: PpuState,
     pub dma: DmaState,
+    #[serde(skip)]
+    pub debug: DebugState,
+    /// Number of M-cycles emulated so far.
+    #[serde(default)]
+    pub cycles: u64,
 }
 
 impl State {
-    pub fn load(rom_or_save: Vec<u8>, ram: Option<Vec<u8>>) -> Result<Self> {
+    pub fn load(rom_or_save: Vec<u8>, ram: Option<Vec<u8>>) -> Result<Self, EmulatorError> {
         if rom_or_save.starts_with(SAVESTATE_TAG) {
-            Self::load_save(&rom_or_save).context("loading savestate")
+            Self::load_save(&rom_or_save).map_err(EmulatorError::CorruptedSavestate)
         } else {
-            Self::load_cartridge(rom_or_save, ram).context("loading cartridge")
+            Self::load_cartridge(rom_or_save, ram).map_err(EmulatorError::cartridge)
         }
     }
 
@@ -43,6 +50,8 @@ impl State {
             cpu: Default::default(),
             ppu: Default::default(),
             dma: Default::default(),
+            debug: Default::default(),
+            cycles: 0,
         })
     }
 
@@ -52,15 +61,17 @@ impl State {
     }
 
     pub fn store_save(&self, path: &Path) -> Result<()> {
-        let mut file = File::create(path).with_context(|| format!("creating {path:?}"))?;
-
-        file.write_all(SAVESTATE_TAG)
-            .with_context(|| format!("writing tag to {path:?}"))?;
-
-        rmp_serde::encode::write_named(&mut file, self)
+        let file = File::create(path).with_context(|| format!("creating {path:?}"))?;
+        self.dump_save(file)
             .with_context(|| format!("writing state to {path:?}"))
     }
 
+    pub fn dump_save<W: Write>(&self, mut w: W) -> Result<()> {
+        w.write_all(SAVESTATE_TAG).context("writing tag")?;
+        rmp_serde::encode::write_named(&mut w, self)?;
+        Ok(())
+    }
+
     pub fn store_ram(&self, path: &Path) -> Result<()> {
         let tmp_path = path.with_extension("ram.tmp");
         let mut file = File::create(&tmp_path).with_context(|| format!("creating {path:?}"))?;
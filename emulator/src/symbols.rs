@@ -0,0 +1,95 @@
+//! Symbol tables, as produced by RGBDS and no$gmb in `.sym` files.
+//!
+//! Each line of a `.sym` file holds a bank, an address and a label, like `01:4000 Main`.
+//! Comments start with a `;`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{bail, Context, Result};
+
+use crate::debugger::{Breakpoint, Location};
+use crate::mmu;
+
+#[derive(Debug, Default)]
+pub struct Symbols {
+    labels: BTreeMap<Location, String>,
+    locations: HashMap<String, Location>,
+}
+
+impl Symbols {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut symbols = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (location, label) =
+                parse_line(line).with_context(|| format!("invalid symbol on line {}", i + 1))?;
+            symbols.labels.insert(location, label.into());
+            symbols.locations.insert(label.into(), location);
+        }
+
+        Ok(symbols)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Return the location of the symbol with the given name.
+    pub fn location(&self, name: &str) -> Option<Location> {
+        self.locations.get(name).copied()
+    }
+
+    /// Return a breakpoint at the symbol with the given name.
+    ///
+    /// Breakpoints in switchable memory regions only trigger in the symbol's bank.
+    pub fn breakpoint(&self, name: &str) -> Option<Breakpoint> {
+        let location = self.location(name)?;
+        let bank = match location.addr {
+            0x4000..=0x7fff | 0xa000..=0xbfff => Some(location.bank),
+            _ => None,
+        };
+
+        Some(Breakpoint {
+            bank,
+            ..Breakpoint::at(location.addr)
+        })
+    }
+
+    /// Describe a location as `label+offset`, relative to the closest preceding symbol in the same
+    /// bank and memory region.
+    pub fn label(&self, location: Location) -> Option<String> {
+        let start = Location {
+            bank: location.bank,
+            addr: 0,
+        };
+        let (symbol, label) = self.labels.range(start..=location).next_back()?;
+        if mmu::region_at(symbol.addr) != mmu::region_at(location.addr) {
+            return None;
+        }
+
+        let offset = location.addr - symbol.addr;
+        match offset {
+            0 => Some(label.clone()),
+            _ => Some(format!("{label}+{offset:#x}")),
+        }
+    }
+}
+
+fn parse_line(line: &str) -> Result<(Location, &str)> {
+    let mut parts = line.split_whitespace();
+    let (Some(location), Some(label), None) = (parts.next(), parts.next(), parts.next()) else {
+        bail!("expected `bank:address label`");
+    };
+    let Some((bank, addr)) = location.split_once(':') else {
+        bail!("expected `bank:address`, got {location:?}");
+    };
+
+    let bank = u16::from_str_radix(bank, 16).with_context(|| format!("invalid bank: {bank}"))?;
+    let addr = u16::from_str_radix(addr, 16).with_context(|| format!("invalid address: {addr}"))?;
+    Ok((Location { bank, addr }, label))
+}
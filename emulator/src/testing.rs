@@ -0,0 +1,51 @@
+//! Helpers for driving the emulator from unit tests.
+
+use crate::state::State;
+use crate::{Emulator, StopReason};
+
+/// Number of frames to run before giving up on a debugger stop.
+const FRAME_LIMIT: usize = 10;
+
+/// Build a ROM-only cartridge that runs `code` from 0x0150.
+///
+/// The interrupt vectors contain endless loops, so a dispatched interrupt parks the CPU there.
+pub(crate) fn rom(code: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    for vector in (0x40..=0x60).step_by(8) {
+        rom[vector..vector + 2].copy_from_slice(&[0x18, 0xfe]); // jr -2
+    }
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]); // nop; jp 0x0150
+    rom[0x14d] = rom[0x134..0x14d]
+        .iter()
+        .fold(0, |sum: u8, b| sum.wrapping_sub(*b).wrapping_sub(1));
+    rom[0x150..0x150 + code.len()].copy_from_slice(code);
+    rom
+}
+
+pub(crate) fn state(code: &[u8]) -> State {
+    State::load(rom(code), None).unwrap()
+}
+
+pub(crate) fn emulator(code: &[u8]) -> Emulator {
+    Emulator::load(rom(code), None).unwrap()
+}
+
+/// Run until the debugger stops execution, skipping completed frames.
+pub(crate) fn run_until_stop(emu: &mut Emulator) -> StopReason {
+    for _ in 0..FRAME_LIMIT {
+        match emu.run_until_break() {
+            StopReason::Frame(_) => (),
+            reason => return reason,
+        }
+    }
+    panic!("debugger didn't stop");
+}
+
+/// Run until the CPU is about to fetch the instruction at `addr`.
+pub(crate) fn run_to(emu: &mut Emulator, addr: u16) {
+    emu.debugger().run_to(addr);
+    match run_until_stop(emu) {
+        StopReason::Step => (),
+        reason => panic!("unexpected stop: {reason:?}"),
+    }
+}
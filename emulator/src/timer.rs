@@ -1,3 +1,5 @@
+use std::mem;
+
 use log::trace;
 
 use crate::bits::BitsExt;
@@ -18,37 +20,19 @@ impl<'a> Timer<'a> {
     }
 
     pub fn step(&mut self) {
-        let counter = self.t.counter.wrapping_add(4);
-        self.t.counter = counter;
-
-        let tac = self.t.tac;
-        if tac.enable() {
-            let should_tick = match tac.clock_select() {
-                0b00 => counter.bits(0..=9) == 0,
-                0b01 => counter.bits(0..=3) == 0,
-                0b10 => counter.bits(0..=5) == 0,
-                0b11 => counter.bits(0..=7) == 0,
-                _ => unreachable!(),
-            };
-
-            if should_tick {
-                self.tick_timer();
-            }
-        }
-    }
-
-    fn tick_timer(&mut self) {
-        let tima = self.t.tima.wrapping_add(1);
-        self.t.tima = tima;
-        trace!("tick: {tima:#x}");
-
-        if tima == 0 {
-            self.interrupts.set_flag(Interrupt::Timer);
+        self.t.reloaded = false;
+        if mem::take(&mut self.t.overflow) {
             self.t.tima = self.t.tma;
+            self.t.reloaded = true;
+            self.interrupts.set_flag(Interrupt::Timer);
         }
+
+        let counter = self.t.counter.wrapping_add(4);
+        self.t.set_counter(counter);
     }
 }
 
+/// Reference: https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html
 #[derive(Debug, Default)]
 #[derive(serde::Serialize, serde::Deserialize)]
 pub(crate) struct TimerState {
@@ -56,6 +40,12 @@ pub(crate) struct TimerState {
     tima: u8,
     tma: u8,
     tac: Tac,
+    /// Whether TIMA overflowed in the last M-cycle and reads 0 until it is reloaded.
+    #[serde(default)]
+    overflow: bool,
+    /// Whether TIMA was reloaded from TMA in the current M-cycle.
+    #[serde(default)]
+    reloaded: bool,
 }
 
 impl TimerState {
@@ -64,7 +54,12 @@ impl TimerState {
     }
 
     pub fn write_div(&mut self, _value: u8) {
-        self.counter = 0;
+        self.set_counter(0);
+    }
+
+    /// Set the DIV register without resetting the divider or ticking TIMA.
+    pub fn poke_div(&mut self, value: u8) {
+        self.counter = u16::from(value) << 8 | self.counter & 0xff;
     }
 
     pub fn read_tima(&self) -> u8 {
@@ -72,6 +67,16 @@ impl TimerState {
     }
 
     pub fn write_tima(&mut self, value: u8) {
+        // Writes in the cycle TIMA is reloaded are ignored, while writes before it cancel the
+        // reload.
+        if !self.reloaded {
+            self.tima = value;
+            self.overflow = false;
+        }
+    }
+
+    /// Set TIMA without affecting a pending reload.
+    pub fn poke_tima(&mut self, value: u8) {
         self.tima = value;
     }
 
@@ -81,6 +86,14 @@ impl TimerState {
 
     pub fn write_tma(&mut self, value: u8) {
         self.tma = value;
+        if self.reloaded {
+            self.tima = value;
+        }
+    }
+
+    /// Set TMA without reloading TIMA.
+    pub fn poke_tma(&mut self, value: u8) {
+        self.tma = value;
     }
 
     pub fn read_tac(&self) -> u8 {
@@ -88,7 +101,40 @@ impl TimerState {
     }
 
     pub fn write_tac(&mut self, value: u8) {
+        let signal = self.signal();
         self.tac = Tac(value.bits(0..=2));
+        self.check_falling_edge(signal);
+    }
+
+    /// Set the TAC register without ticking TIMA.
+    pub fn poke_tac(&mut self, value: u8) {
+        self.tac = Tac(value.bits(0..=2));
+    }
+
+    /// Set the system counter, ticking TIMA if that causes a falling edge.
+    fn set_counter(&mut self, counter: u16) {
+        let signal = self.signal();
+        self.counter = counter;
+        self.check_falling_edge(signal);
+    }
+
+    /// TIMA ticks on the falling edges of the selected counter bit, ANDed with the enable bit.
+    fn signal(&self) -> bool {
+        self.tac.enable() && self.counter.bit(self.tac.counter_bit())
+    }
+
+    fn check_falling_edge(&mut self, prev_signal: bool) {
+        if prev_signal && !self.signal() {
+            self.tick_timer();
+        }
+    }
+
+    fn tick_timer(&mut self) {
+        let tima = self.tima.wrapping_add(1);
+        self.tima = tima;
+        trace!("tick: {tima:#x}");
+
+        self.overflow = tima == 0;
     }
 }
 
@@ -101,7 +147,96 @@ impl Tac {
         self.0.bit(2)
     }
 
-    fn clock_select(&self) -> u8 {
-        self.0.bits(0..=1)
+    /// The bit of the system counter that clocks TIMA.
+    fn counter_bit(&self) -> u8 {
+        match self.0.bits(0..=1) {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            0b11 => 7,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// TIMA ticks every 4 M-cycles, the first time after 4 steps.
+    fn state() -> State {
+        let mut s = testing::state(&[]);
+        s.timer = TimerState::default();
+        s.timer.write_tac(0b101);
+        s.cpu.interrupts.write_flag(0);
+        s
+    }
+
+    fn step(s: &mut State, cycles: usize) {
+        (0..cycles).for_each(|_| Timer::new(s).step());
+    }
+
+    fn timer_flag(s: &State) -> bool {
+        s.cpu.interrupts.read_flag().bit(Interrupt::Timer as u8)
+    }
+
+    #[test]
+    fn overflow_reloads_one_cycle_later() {
+        let mut s = state();
+        s.timer.tima = 0xff;
+        s.timer.tma = 0x42;
+
+        step(&mut s, 4);
+        assert_eq!(s.timer.read_tima(), 0x00);
+        assert!(!timer_flag(&s));
+
+        step(&mut s, 1);
+        assert_eq!(s.timer.read_tima(), 0x42);
+        assert!(timer_flag(&s));
+    }
+
+    #[test]
+    fn write_during_overflow_cancels_reload() {
+        let mut s = state();
+        s.timer.tima = 0xff;
+        s.timer.tma = 0x42;
+
+        step(&mut s, 4);
+        s.timer.write_tima(0x10);
+        step(&mut s, 1);
+        assert_eq!(s.timer.read_tima(), 0x10);
+        assert!(!timer_flag(&s));
+    }
+
+    #[test]
+    fn write_during_reload_is_ignored() {
+        let mut s = state();
+        s.timer.tima = 0xff;
+        s.timer.tma = 0x42;
+
+        step(&mut s, 5);
+        s.timer.write_tima(0x10);
+        assert_eq!(s.timer.read_tima(), 0x42);
+
+        s.timer.write_tma(0x55);
+        assert_eq!(s.timer.read_tima(), 0x55);
+
+        step(&mut s, 1);
+        s.timer.write_tima(0x10);
+        assert_eq!(s.timer.read_tima(), 0x10);
+    }
+
+    #[test]
+    fn tac_write_ticks_on_falling_edge() {
+        let mut s = state();
+        step(&mut s, 2);
+
+        s.timer.poke_tac(0b001);
+        assert_eq!(s.timer.read_tima(), 0);
+        s.timer.poke_tac(0b101);
+
+        s.timer.write_tac(0b001);
+        assert_eq!(s.timer.read_tima(), 1);
     }
 }
@@ -0,0 +1,111 @@
+//! Per-instruction execution traces.
+//!
+//! Traces are written in the format used by gameboy-doctor
+//! (https://github.com/robert/gameboy-doctor), so they can be compared to traces from other
+//! emulators. The annotated format extends each line with the cycle count, the ROM bank, the
+//! instruction's label, if symbols are loaded, and the disassembled instruction.
+
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use log::error;
+
+use crate::debugger::{decode_at, label_at};
+use crate::mmu::Mmu;
+use crate::state::State;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// The plain gameboy-doctor format.
+    #[default]
+    Doctor,
+    /// The gameboy-doctor format, annotated with cycle count, ROM bank and disassembly.
+    Annotated,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "doctor" => Ok(Self::Doctor),
+            "annotated" => Ok(Self::Annotated),
+            _ => Err(format!("invalid trace format: {s}")),
+        }
+    }
+}
+
+pub(crate) struct Tracer {
+    out: Box<dyn Write>,
+    format: TraceFormat,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>, format: TraceFormat) -> Self {
+        Self { out, format }
+    }
+
+    /// Write the trace line for the instruction at the current PC.
+    ///
+    /// Returns `false` if writing failed and tracing should stop.
+    pub fn record(&mut self, state: &mut State) -> bool {
+        let line = self.format_line(state);
+        match writeln!(self.out, "{line}") {
+            Ok(()) => true,
+            Err(error) => {
+                error!("cannot write trace: {error}");
+                false
+            }
+        }
+    }
+
+    fn format_line(&self, state: &mut State) -> String {
+        let cpu = state.cpu.snapshot();
+        let pc = cpu.pc;
+        let mmu = Mmu::new(state);
+        let pcmem = [0, 1, 2, 3].map(|i| mmu.peek(pc.wrapping_add(i)));
+
+        let mut line = format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} \
+             SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            cpu.a,
+            cpu.f,
+            cpu.b,
+            cpu.c,
+            cpu.d,
+            cpu.e,
+            cpu.h,
+            cpu.l,
+            cpu.sp,
+            pc,
+            pcmem[0],
+            pcmem[1],
+            pcmem[2],
+            pcmem[3],
+        );
+
+        if self.format == TraceFormat::Annotated {
+            let bank = state.mmu.bank_at(pc);
+            let disasm = match decode_at(state, pc) {
+                Some((inst, _)) => inst.to_string(),
+                None => "???".into(),
+            };
+            line += &format!(" CY:{} BANK:{bank:02X} ", state.cycles);
+            if let Some(label) = label_at(state, pc) {
+                line += &format!("{label}: ");
+            }
+            line += &disasm;
+        }
+
+        line
+    }
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
+}
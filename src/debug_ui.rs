@@ -0,0 +1,642 @@
+//! A debugger overlay for the desktop frontend, built with egui.
+
+use std::mem;
+
+use egui::{
+    Color32, ColorImage, ComboBox, Context, DragValue, Grid, RichText, ScrollArea, TextStyle,
+    TextureHandle, TextureOptions,
+};
+use egui_wgpu::renderer::{Renderer, ScreenDescriptor};
+use egui_wgpu::wgpu;
+use pixels::{Pixels, PixelsContext};
+use winit::event::WindowEvent;
+use winit::event_loop::EventLoopWindowTarget;
+use winit::window::Window;
+
+use emulator::{Breakpoint, CpuSnapshot, Emulator, Image, Location, Region, TileMap};
+
+/// Number of instructions shown in the disassembly.
+const DISASM_LINES: usize = 20;
+/// Number of instructions shown before the current one in the disassembly.
+const DISASM_CONTEXT: usize = 4;
+const MEMORY_ROW_SIZE: u16 = 16;
+/// Scale at which VRAM images are shown.
+const VRAM_SCALE: f32 = 2.;
+
+const INTERRUPTS: [&str; 5] = ["VBlank", "LCD", "Timer", "Serial", "Joypad"];
+
+/// A debugger command requested through the UI.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Command {
+    Pause,
+    Step,
+    StepOver,
+    StepOut,
+    Continue,
+}
+
+pub(crate) struct DebugUi {
+    visible: bool,
+    ctx: Context,
+    egui_state: egui_winit::State,
+    screen: ScreenDescriptor,
+    renderer: Renderer,
+    paint_jobs: Vec<egui::ClippedPrimitive>,
+    textures: egui::TexturesDelta,
+    breakpoints: BreakpointView,
+    memory: MemoryView,
+    vram: VramView,
+}
+
+impl DebugUi {
+    pub fn new<T>(event_loop: &EventLoopWindowTarget<T>, window: &Window, pixels: &Pixels) -> Self {
+        let size = window.inner_size();
+        let scale_factor = window.scale_factor() as f32;
+        let max_texture_side = pixels.device().limits().max_texture_dimension_2d;
+
+        let mut egui_state = egui_winit::State::new(event_loop);
+        egui_state.set_max_texture_side(max_texture_side as usize);
+        egui_state.set_pixels_per_point(scale_factor);
+
+        Self {
+            visible: false,
+            ctx: Context::default(),
+            egui_state,
+            screen: ScreenDescriptor {
+                size_in_pixels: [size.width, size.height],
+                pixels_per_point: scale_factor,
+            },
+            renderer: Renderer::new(pixels.device(), pixels.render_texture_format(), None, 1),
+            paint_jobs: Vec::new(),
+            textures: Default::default(),
+            breakpoints: BreakpointView::default(),
+            memory: MemoryView::default(),
+            vram: VramView::default(),
+        }
+    }
+
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Whether the UI currently wants to receive keyboard input, e.g. for a text field.
+    pub fn wants_keyboard(&self) -> bool {
+        self.visible && self.ctx.wants_keyboard_input()
+    }
+
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
+                self.screen.size_in_pixels = [size.width, size.height];
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.screen.pixels_per_point = *scale_factor as f32;
+            }
+            _ => (),
+        }
+
+        if self.visible {
+            let _ = self.egui_state.on_event(&self.ctx, event);
+        }
+    }
+
+    /// Build the UI for the current emulator state and return the requested debugger commands.
+    pub fn prepare(&mut self, window: &Window, emu: &mut Emulator, status: &str) -> Vec<Command> {
+        let mut commands = Vec::new();
+
+        let input = self.egui_state.take_egui_input(window);
+        let output = self.ctx.run(input, |ctx| {
+            controls_window(ctx, emu, status, &mut commands);
+            cpu_window(ctx, emu);
+            disassembly_window(ctx, emu);
+            self.breakpoints.window(ctx, emu);
+            self.memory.window(ctx, emu);
+            self.vram.window(ctx, emu);
+        });
+
+        self.textures.append(output.textures_delta);
+        self.egui_state
+            .handle_platform_output(window, &self.ctx, output.platform_output);
+        self.paint_jobs = self.ctx.tessellate(output.shapes);
+
+        commands
+    }
+
+    /// Render the UI prepared by the last call to `prepare`.
+    pub fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        context: &PixelsContext,
+    ) {
+        let device = &context.device;
+        let queue = &context.queue;
+
+        for (id, delta) in &self.textures.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        self.renderer
+            .update_buffers(device, queue, encoder, &self.paint_jobs, &self.screen);
+
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("egui"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            self.renderer
+                .render(&mut pass, &self.paint_jobs, &self.screen);
+        }
+
+        for id in &mem::take(&mut self.textures).free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
+
+fn controls_window(ctx: &Context, emu: &mut Emulator, status: &str, commands: &mut Vec<Command>) {
+    egui::Window::new("Debugger").show(ctx, |ui| {
+        ui.label(status);
+        ui.horizontal(|ui| {
+            let buttons = [
+                ("Pause", Command::Pause),
+                ("Step", Command::Step),
+                ("Step Over", Command::StepOver),
+                ("Step Out", Command::StepOut),
+                ("Continue", Command::Continue),
+            ];
+            for (label, command) in buttons {
+                if ui.button(label).clicked() {
+                    commands.push(command);
+                }
+            }
+        });
+
+        ui.separator();
+        let mut layers = emu.layers();
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut layers.background, "Background");
+            ui.checkbox(&mut layers.window, "Window");
+            ui.checkbox(&mut layers.objects, "Objects");
+            ui.checkbox(&mut layers.object_boxes, "Object boxes");
+        });
+        if layers != emu.layers() {
+            emu.set_layers(layers);
+        }
+    });
+}
+
+fn cpu_window(ctx: &Context, emu: &Emulator) {
+    let cpu = emu.cpu();
+    let ie = emu.peek(0xffff);
+    let if_ = emu.peek(0xff0f);
+
+    egui::Window::new("CPU").show(ctx, |ui| {
+        Grid::new("registers").show(ui, |ui| {
+            let registers = [
+                ("AF", cpu.af()),
+                ("BC", cpu.bc()),
+                ("DE", cpu.de()),
+                ("HL", cpu.hl()),
+                ("SP", cpu.sp),
+                ("PC", cpu.pc),
+            ];
+            for (name, value) in registers {
+                ui.label(name);
+                ui.monospace(format!("{value:04x}"));
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            for (name, bit) in [("Z", 7), ("N", 6), ("H", 5), ("C", 4)] {
+                flag(ui, name, cpu.f & (1 << bit) != 0);
+            }
+        });
+        ui.horizontal(|ui| {
+            flag(ui, "IME", cpu.ime);
+            flag(ui, "HALT", cpu.halted);
+            flag(ui, "STOP", cpu.stopped);
+        });
+
+        ui.separator();
+        Grid::new("interrupts").show(ui, |ui| {
+            ui.label("");
+            ui.label("IE");
+            ui.label("IF");
+            ui.end_row();
+            for (bit, name) in INTERRUPTS.iter().enumerate() {
+                ui.label(*name);
+                ui.monospace(bit_char(ie, bit));
+                ui.monospace(bit_char(if_, bit));
+                ui.end_row();
+            }
+        });
+    });
+}
+
+fn flag(ui: &mut egui::Ui, name: &str, set: bool) {
+    let color = if set { Color32::LIGHT_GREEN } else { Color32::DARK_GRAY };
+    ui.label(RichText::new(name).monospace().color(color));
+}
+
+fn bit_char(value: u8, bit: usize) -> &'static str {
+    if value & (1 << bit) != 0 {
+        "1"
+    } else {
+        "0"
+    }
+}
+
+fn disassembly_window(ctx: &Context, emu: &Emulator) {
+    let cpu = emu.cpu();
+    let lines = disassemble(emu, &cpu);
+
+    egui::Window::new("Disassembly").show(ctx, |ui| {
+        for line in lines {
+            if let Some(label) = line.label {
+                ui.monospace(format!("{label}:"));
+            }
+
+            let text = format!("{:04x}  {:<9} {}", line.addr, line.bytes, line.text);
+            let mut text = RichText::new(text).monospace();
+            if line.addr == cpu.pc {
+                text = text.color(Color32::YELLOW);
+            }
+            ui.label(text);
+        }
+    });
+}
+
+struct DisasmLine {
+    addr: u16,
+    bytes: String,
+    text: String,
+    /// The label of a symbol starting at this address.
+    label: Option<String>,
+}
+
+/// Disassemble the instructions around the current PC.
+///
+/// Instructions have variable lengths, so we can't simply disassemble backwards. Instead, we look
+/// for the furthest start address before the PC from which decoding lands exactly on the PC.
+fn disassemble(emu: &Emulator, cpu: &CpuSnapshot) -> Vec<DisasmLine> {
+    let mut start = cpu.pc;
+    for back in (1..=(DISASM_CONTEXT as u16 * 3)).rev() {
+        let addr = cpu.pc.wrapping_sub(back);
+        let mut a = addr;
+        let mut count = 0;
+        while a != cpu.pc && count <= DISASM_CONTEXT {
+            let Some((_, len)) = emu.decode(a) else { break };
+            a = a.wrapping_add(len);
+            count += 1;
+        }
+        if a == cpu.pc && count <= DISASM_CONTEXT {
+            start = addr;
+            break;
+        }
+    }
+
+    let mut lines = Vec::with_capacity(DISASM_LINES);
+    let mut addr = start;
+    for _ in 0..DISASM_LINES {
+        let decoded = emu.decode(addr);
+        let (mut text, len) = match decoded {
+            Some((inst, len)) => (inst.to_string(), len),
+            None => ("???".into(), 1),
+        };
+        let next = addr.wrapping_add(len);
+
+        let target = decoded.and_then(|(inst, _)| inst.jump_target(next));
+        if let Some(label) = target.and_then(|t| emu.label(t)) {
+            text += &format!("  ; {label}");
+        }
+
+        let bytes: Vec<_> = (0..len)
+            .map(|i| format!("{:02x}", emu.peek(addr.wrapping_add(i))))
+            .collect();
+        lines.push(DisasmLine {
+            addr,
+            bytes: bytes.join(" "),
+            text,
+            label: emu.label(addr).filter(|l| !l.contains('+')),
+        });
+        addr = next;
+    }
+    lines
+}
+
+#[derive(Default)]
+struct BreakpointView {
+    /// The address or symbol entered for a new breakpoint.
+    input: String,
+    error: Option<String>,
+}
+
+impl BreakpointView {
+    fn window(&mut self, ctx: &Context, emu: &mut Emulator) {
+        egui::Window::new("Breakpoints").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let input = ui.text_edit_singleline(&mut self.input);
+                let submitted = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.button("Add").clicked() || submitted {
+                    self.add(emu);
+                }
+            });
+            if let Some(error) = &self.error {
+                ui.colored_label(Color32::LIGHT_RED, error);
+            }
+
+            let breakpoints: Vec<_> = emu
+                .debugger()
+                .breakpoints()
+                .map(|(id, bp)| (id, bp.clone()))
+                .collect();
+
+            let mut removed = None;
+            for (id, bp) in breakpoints {
+                let addr = bp.addr;
+                let label = match bp.bank {
+                    Some(bank) => emu.symbols().label(Location { bank, addr }),
+                    None => emu.label(addr),
+                };
+
+                ui.horizontal(|ui| {
+                    if ui.small_button("x").clicked() {
+                        removed = Some(id);
+                    }
+                    let bank = bp.bank.map(|b| format!("{b:02x}:")).unwrap_or_default();
+                    let label = label.map(|l| format!(" ({l})")).unwrap_or_default();
+                    ui.monospace(format!("#{id} {bank}{addr:04x}{label}"));
+                });
+            }
+            if let Some(id) = removed {
+                emu.debugger().remove_breakpoint(id);
+            }
+        });
+    }
+
+    /// Add a breakpoint at the entered symbol or hex address.
+    fn add(&mut self, emu: &mut Emulator) {
+        let input = self.input.trim();
+        let mut debugger = emu.debugger();
+
+        self.error = None;
+        if debugger.add_breakpoint_at_symbol(input).is_some() {
+            self.input.clear();
+            return;
+        }
+
+        let hex = input.trim_start_matches("0x").trim_start_matches('$');
+        match u16::from_str_radix(hex, 16) {
+            Ok(addr) => {
+                debugger.add_breakpoint(Breakpoint::at(addr));
+                self.input.clear();
+            }
+            Err(_) => self.error = Some(format!("unknown symbol: {input}")),
+        }
+    }
+}
+
+/// The memory region shown by the memory viewer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Space {
+    /// The CPU's address space, with the current bank mapping.
+    Bus,
+    Region(Region),
+}
+
+impl Space {
+    const ALL: [Self; 7] = [
+        Self::Bus,
+        Self::Region(Region::Rom),
+        Self::Region(Region::CartRam),
+        Self::Region(Region::Vram),
+        Self::Region(Region::Wram),
+        Self::Region(Region::Oam),
+        Self::Region(Region::Hram),
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Bus => "Address space",
+            Self::Region(Region::Rom) => "ROM",
+            Self::Region(Region::CartRam) => "Cartridge RAM",
+            Self::Region(Region::Vram) => "VRAM",
+            Self::Region(Region::Wram) => "WRAM",
+            Self::Region(Region::Oam) => "OAM",
+            Self::Region(Region::Hram) => "HRAM",
+        }
+    }
+
+    /// Return the size of a bank and the address of its first byte, as shown to the user.
+    fn layout(&self) -> (u32, u16) {
+        match self {
+            Self::Bus => (0x10000, 0x0000),
+            Self::Region(Region::Rom) => (0x4000, 0x0000),
+            Self::Region(Region::CartRam) => (0x2000, 0xa000),
+            Self::Region(Region::Vram) => (0x2000, 0x8000),
+            Self::Region(Region::Wram) => (0x2000, 0xc000),
+            Self::Region(Region::Oam) => (0xa0, 0xfe00),
+            Self::Region(Region::Hram) => (0x7f, 0xff80),
+        }
+    }
+}
+
+struct MemoryView {
+    space: Space,
+    bank: u16,
+}
+
+impl Default for MemoryView {
+    fn default() -> Self {
+        Self {
+            space: Space::Bus,
+            bank: 0,
+        }
+    }
+}
+
+impl MemoryView {
+    fn window(&mut self, ctx: &Context, emu: &Emulator) {
+        egui::Window::new("Memory").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ComboBox::from_id_source("memory-space")
+                    .selected_text(self.space.name())
+                    .show_ui(ui, |ui| {
+                        for space in Space::ALL {
+                            ui.selectable_value(&mut self.space, space, space.name());
+                        }
+                    });
+
+                if let Space::Region(region) = self.space {
+                    let banks = emu.banks(region);
+                    ui.label("Bank");
+                    ui.add(DragValue::new(&mut self.bank).clamp_range(0..=banks.saturating_sub(1)));
+                    self.bank = self.bank.min(banks.saturating_sub(1));
+                }
+            });
+
+            let (size, base) = self.space.layout();
+            let row_height = ui.text_style_height(&TextStyle::Monospace);
+            let rows = (size as usize).div_ceil(usize::from(MEMORY_ROW_SIZE));
+
+            ScrollArea::vertical().show_rows(ui, row_height, rows, |ui, rows| {
+                for row in rows {
+                    let offset = row as u16 * MEMORY_ROW_SIZE;
+                    let line = self.format_row(emu, offset, size);
+                    let addr = base.wrapping_add(offset);
+                    ui.monospace(format!("{addr:04x}  {line}"));
+                }
+            });
+        });
+    }
+
+    fn format_row(&self, emu: &Emulator, offset: u16, size: u32) -> String {
+        let mut hex = String::new();
+        let mut ascii = String::new();
+        for i in 0..MEMORY_ROW_SIZE {
+            let offset = offset + i;
+            let value = if u32::from(offset) >= size {
+                None
+            } else {
+                match self.space {
+                    Space::Bus => Some(emu.peek(offset)),
+                    Space::Region(region) => emu.peek_banked(region, self.bank, offset),
+                }
+            };
+
+            match value {
+                Some(v) => {
+                    hex += &format!("{v:02x} ");
+                    ascii.push(if v.is_ascii_graphic() { v as char } else { '.' });
+                }
+                None => {
+                    hex += "-- ";
+                    ascii.push(' ');
+                }
+            }
+        }
+        format!("{hex} {ascii}")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum VramTab {
+    Tiles,
+    Map(TileMap),
+    Objects,
+}
+
+struct VramView {
+    tab: VramTab,
+    textures: Vec<TextureHandle>,
+}
+
+impl Default for VramView {
+    fn default() -> Self {
+        Self {
+            tab: VramTab::Tiles,
+            textures: Vec::new(),
+        }
+    }
+}
+
+impl VramView {
+    fn window(&mut self, ctx: &Context, emu: &Emulator) {
+        egui::Window::new("VRAM").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut self.tab, VramTab::Tiles, "Tiles");
+                ui.selectable_value(&mut self.tab, VramTab::Map(TileMap::Low), "Map 9800");
+                ui.selectable_value(&mut self.tab, VramTab::Map(TileMap::High), "Map 9c00");
+                ui.selectable_value(&mut self.tab, VramTab::Objects, "OAM");
+            });
+            ui.separator();
+
+            match self.tab {
+                VramTab::Tiles => {
+                    let image = emu.render_tiles();
+                    self.show_images(ui, [image]);
+                }
+                VramTab::Map(map) => {
+                    let image = emu.render_tile_map(map);
+                    self.show_images(ui, [image]);
+                }
+                VramTab::Objects => self.show_objects(ui, emu),
+            }
+        });
+    }
+
+    fn show_images(&mut self, ui: &mut egui::Ui, images: impl IntoIterator<Item = Image>) {
+        self.update_textures(ui.ctx(), images);
+        for texture in &self.textures {
+            ui.image(texture, texture.size_vec2() * VRAM_SCALE);
+        }
+    }
+
+    fn show_objects(&mut self, ui: &mut egui::Ui, emu: &Emulator) {
+        let objects = emu.objects();
+        self.update_textures(ui.ctx(), objects.iter().map(|obj| obj.image.clone()));
+
+        ScrollArea::vertical().show(ui, |ui| {
+            Grid::new("objects").striped(true).show(ui, |ui| {
+                for header in ["", "#", "X", "Y", "Tile", "Palette", "Flip", "Priority"] {
+                    ui.label(header);
+                }
+                ui.end_row();
+
+                for (i, (obj, texture)) in objects.iter().zip(&self.textures).enumerate() {
+                    let a = &obj.attributes;
+                    let flip = match (a.flags.x_flip(), a.flags.y_flip()) {
+                        (false, false) => "-",
+                        (true, false) => "X",
+                        (false, true) => "Y",
+                        (true, true) => "XY",
+                    };
+
+                    ui.image(texture, texture.size_vec2() * VRAM_SCALE);
+                    ui.monospace(format!("{i:02}"));
+                    ui.monospace(format!("{:3}", a.x));
+                    ui.monospace(format!("{:3}", a.y));
+                    ui.monospace(format!("{:02x}", a.tile_id));
+                    ui.monospace(format!("OBP{}", u8::from(a.flags.palette())));
+                    ui.monospace(flip);
+                    ui.monospace(if a.flags.bg_over_obj() { "BG" } else { "OBJ" });
+                    ui.end_row();
+                }
+            });
+        });
+    }
+
+    /// Upload the given images into textures, reusing the existing textures where possible.
+    fn update_textures(&mut self, ctx: &Context, images: impl IntoIterator<Item = Image>) {
+        let mut count = 0;
+        for (i, image) in images.into_iter().enumerate() {
+            let size = [image.width() as usize, image.height() as usize];
+            let color_image = ColorImage::from_rgba_unmultiplied(size, image.rgba());
+
+            match self.textures.get_mut(i) {
+                Some(texture) => texture.set(color_image, TextureOptions::NEAREST),
+                None => {
+                    let name = format!("vram-{i}");
+                    let texture = ctx.load_texture(name, color_image, TextureOptions::NEAREST);
+                    self.textures.push(texture);
+                }
+            }
+            count += 1;
+        }
+        self.textures.truncate(count);
+    }
+}
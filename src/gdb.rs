@@ -0,0 +1,673 @@
+//! A stub for the GDB remote serial protocol.
+//!
+//! Reference: https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+
+use anyhow::{bail, Context, Result};
+use log::{debug, info};
+
+use emulator::{Access, Breakpoint, Emulator, Register, StopReason, Watchpoint};
+
+/// The registers exposed to GDB, in GDB's register numbering.
+const REGISTERS: [(Register, &str); 10] = [
+    (Register::A, "a"),
+    (Register::F, "f"),
+    (Register::B, "b"),
+    (Register::C, "c"),
+    (Register::D, "d"),
+    (Register::E, "e"),
+    (Register::H, "h"),
+    (Register::L, "l"),
+    (Register::SP, "sp"),
+    (Register::PC, "pc"),
+];
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+pub(crate) struct GdbStub {
+    stream: TcpStream,
+    /// Received data that doesn't form a complete packet yet.
+    input: Vec<u8>,
+    running: bool,
+    /// Whether the user requested to interrupt execution.
+    interrupted: bool,
+    /// The reply describing why execution last stopped.
+    stop_reply: String,
+    /// Debugger IDs of the breakpoints and watchpoints inserted by GDB.
+    points: HashMap<(u8, u16, u16), u32>,
+}
+
+/// The state of the GDB connection after processing incoming packets.
+pub(crate) enum Connection {
+    Attached,
+    Detached,
+    Killed,
+}
+
+impl GdbStub {
+    /// Listen on the given port and wait for a GDB client to connect.
+    pub fn listen(port: u16) -> Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .with_context(|| format!("binding GDB port {port}"))?;
+
+        info!("waiting for GDB connection on port {port}");
+        let (stream, peer) = listener.accept().context("accepting GDB connection")?;
+        info!("GDB connected from {peer}");
+
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            input: Vec::new(),
+            running: false,
+            interrupted: false,
+            stop_reply: format!("S{SIGTRAP:02x}"),
+            points: HashMap::new(),
+        })
+    }
+
+    /// Whether the emulator should currently run.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Process all pending packets from the GDB client.
+    pub fn poll(&mut self, emu: &mut Emulator) -> Result<Connection> {
+        if !self.receive()? {
+            info!("GDB disconnected");
+            self.detach(emu);
+            return Ok(Connection::Detached);
+        }
+
+        while let Some(packet) = self.next_packet()? {
+            debug!("gdb <- {}", String::from_utf8_lossy(&packet));
+
+            match packet.first() {
+                Some(b'D') => {
+                    self.send("OK")?;
+                    self.detach(emu);
+                    return Ok(Connection::Detached);
+                }
+                Some(b'k') => return Ok(Connection::Killed),
+                _ => {
+                    if let Some(response) = self.handle(&packet, emu) {
+                        self.send(&response)?;
+                    }
+                }
+            }
+        }
+
+        Ok(Connection::Attached)
+    }
+
+    /// Report that execution stopped.
+    pub fn report_stop(&mut self, reason: &StopReason) -> Result<()> {
+        self.running = false;
+
+        let reply = stop_reply(reason, self.interrupted);
+        self.interrupted = false;
+        self.send(&reply)?;
+        self.stop_reply = reply;
+        Ok(())
+    }
+
+    /// Read all available input, returning `false` if the connection was closed.
+    fn receive(&mut self) -> Result<bool> {
+        self.stream.set_nonblocking(true)?;
+
+        let mut buf = [0; 1024];
+        let result = loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => break Ok(false),
+                Ok(n) => self.input.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(true),
+                Err(e) => break Err(e.into()),
+            }
+        };
+
+        self.stream.set_nonblocking(false)?;
+        result
+    }
+
+    /// Extract the next complete packet from the input buffer, acknowledging it.
+    fn next_packet(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            match take_frame(&mut self.input) {
+                None => return Ok(None),
+                Some(Frame::Interrupt) => return Ok(Some(vec![0x03])),
+                Some(Frame::Packet(data)) => {
+                    self.stream.write_all(b"+")?;
+                    return Ok(Some(data));
+                }
+                Some(Frame::Corrupted) => self.stream.write_all(b"-")?,
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> Result<()> {
+        debug!("gdb -> {data}");
+
+        self.stream.write_all(&encode_packet(data.as_bytes()))?;
+        Ok(())
+    }
+
+    /// Handle a packet and return the response, if any.
+    ///
+    /// Empty responses signal that the packet is not supported.
+    fn handle(&mut self, packet: &[u8], emu: &mut Emulator) -> Option<String> {
+        let Some((cmd, args)) = packet.split_first() else {
+            return Some(String::new());
+        };
+        let args = &*String::from_utf8_lossy(args);
+
+        let response = match cmd {
+            0x03 => {
+                if self.running {
+                    // Stop at the next instruction boundary.
+                    emu.debugger().step_instruction();
+                    self.interrupted = true;
+                }
+                return None;
+            }
+            b'?' => self.stop_reply.clone(),
+            b'g' => read_registers(emu),
+            b'G' => result(write_registers(emu, args)),
+            b'p' => result_or(read_register(emu, args)),
+            b'P' => result(write_register(emu, args)),
+            b'm' => result_or(read_memory(emu, args)),
+            b'M' => result(write_memory(emu, args)),
+            b'Z' => result(self.insert_point(emu, args)),
+            b'z' => result(self.remove_point(emu, args)),
+            b'c' => {
+                emu.debugger().resume();
+                return self.resume(emu, args);
+            }
+            b's' => {
+                emu.debugger().step_instruction();
+                return self.resume(emu, args);
+            }
+            b'H' => "OK".into(),
+            b'q' => query(args),
+            _ => String::new(),
+        };
+        Some(response)
+    }
+
+    /// Resume execution, optionally at the address given in `args`.
+    fn resume(&mut self, emu: &mut Emulator, args: &str) -> Option<String> {
+        if let Ok(addr) = u16::from_str_radix(args, 16) {
+            let mut cpu = emu.cpu();
+            cpu.pc = addr;
+            emu.set_cpu(&cpu);
+        }
+
+        self.running = true;
+        None
+    }
+
+    fn insert_point(&mut self, emu: &mut Emulator, args: &str) -> Result<()> {
+        let key = parse_point(args)?;
+        let (kind, addr, len) = key;
+
+        let mut debugger = emu.debugger();
+        let range = addr..=addr.saturating_add(len.max(1) - 1);
+        let id = match kind {
+            0 | 1 => debugger.add_breakpoint(Breakpoint::at(addr)),
+            2 => debugger.add_watchpoint(Watchpoint {
+                range,
+                access: Access::Write,
+            }),
+            3 => debugger.add_watchpoint(Watchpoint {
+                range,
+                access: Access::Read,
+            }),
+            4 => debugger.add_watchpoint(Watchpoint {
+                range,
+                access: Access::ReadWrite,
+            }),
+            _ => bail!("invalid breakpoint type: {kind}"),
+        };
+
+        if let Some(old) = self.points.insert(key, id) {
+            remove_point(emu, kind, old);
+        }
+        Ok(())
+    }
+
+    fn remove_point(&mut self, emu: &mut Emulator, args: &str) -> Result<()> {
+        let key = parse_point(args)?;
+        if let Some(id) = self.points.remove(&key) {
+            remove_point(emu, key.0, id);
+        }
+        Ok(())
+    }
+
+    fn detach(&mut self, emu: &mut Emulator) {
+        for ((kind, _, _), id) in self.points.drain() {
+            remove_point(emu, kind, id);
+        }
+        emu.debugger().resume();
+        self.running = true;
+    }
+}
+
+/// A unit of data received from the GDB client.
+#[derive(Debug, PartialEq, Eq)]
+enum Frame {
+    /// A packet with a valid checksum, with escapes removed.
+    Packet(Vec<u8>),
+    /// A request to interrupt execution, sent outside of packets.
+    Interrupt,
+    /// A packet with an invalid checksum, which the client needs to retransmit.
+    Corrupted,
+}
+
+/// Remove the next complete frame from `input`, skipping acknowledgements and garbage before it.
+fn take_frame(input: &mut Vec<u8>) -> Option<Frame> {
+    let start = input.iter().position(|b| matches!(b, b'$' | 0x03));
+    input.drain(..start.unwrap_or(input.len()));
+    if input.first() == Some(&0x03) {
+        input.remove(0);
+        return Some(Frame::Interrupt);
+    }
+
+    let end = input.iter().position(|b| *b == b'#')?;
+    if input.len() < end + 3 {
+        return None;
+    }
+
+    let frame: Vec<u8> = input.drain(..end + 3).collect();
+    let data = &frame[1..end];
+    if hex_byte(&frame[end + 1..]) == Some(compute_checksum(data)) {
+        Some(Frame::Packet(unescape(data)))
+    } else {
+        Some(Frame::Corrupted)
+    }
+}
+
+/// Frame `data` as a packet, escaping the characters that have a special meaning.
+fn encode_packet(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &b in data {
+        if matches!(b, b'$' | b'#' | b'}' | b'*') {
+            escaped.extend([b'}', b ^ 0x20]);
+        } else {
+            escaped.push(b);
+        }
+    }
+
+    let checksum = compute_checksum(&escaped);
+    let mut packet = vec![b'$'];
+    packet.append(&mut escaped);
+    packet.extend(format!("#{checksum:02x}").bytes());
+    packet
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&b) = bytes.next() {
+        if b != b'}' {
+            out.push(b);
+        } else if let Some(escaped) = bytes.next() {
+            out.push(escaped ^ 0x20);
+        }
+    }
+    out
+}
+
+fn compute_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, b| sum.wrapping_add(*b))
+}
+
+/// Build the reply that tells GDB why execution stopped.
+fn stop_reply(reason: &StopReason, interrupted: bool) -> String {
+    let signal = match reason {
+        _ if interrupted => SIGINT,
+        StopReason::IllegalOpcode { .. } => SIGILL,
+        _ => SIGTRAP,
+    };
+
+    let mut reply = format!("T{signal:02x}");
+    if let StopReason::Watchpoint { addr, access, .. } = reason {
+        let kind = match access {
+            Access::Write => "watch",
+            Access::Read => "rwatch",
+            _ => "awatch",
+        };
+        write!(reply, "{kind}:{addr:x};").unwrap();
+    }
+    reply
+}
+
+fn result(r: Result<()>) -> String {
+    match r {
+        Ok(()) => "OK".into(),
+        Err(error) => {
+            debug!("gdb request failed: {error:#}");
+            "E01".into()
+        }
+    }
+}
+
+fn result_or(r: Result<String>) -> String {
+    r.unwrap_or_else(|error| {
+        debug!("gdb request failed: {error:#}");
+        "E01".into()
+    })
+}
+
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        "PacketSize=1000;qXfer:features:read+".into()
+    } else if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        read_target_xml(range).unwrap_or_else(|| "E01".into())
+    } else if args == "Attached" {
+        "1".into()
+    } else if args == "fThreadInfo" {
+        "m1".into()
+    } else if args == "sThreadInfo" {
+        "l".into()
+    } else if args == "C" {
+        "QC1".into()
+    } else {
+        String::new()
+    }
+}
+
+/// Return the requested chunk of the target description.
+fn read_target_xml(range: &str) -> Option<String> {
+    let (offset, length) = range.split_once(',')?;
+    let offset = usize::from_str_radix(offset, 16).ok()?;
+    let length = usize::from_str_radix(length, 16).ok()?;
+
+    let xml = target_xml();
+    let start = offset.min(xml.len());
+    let end = start.saturating_add(length).min(xml.len());
+    let marker = if end == xml.len() { 'l' } else { 'm' };
+    Some(format!("{marker}{}", &xml[start..end]))
+}
+
+fn target_xml() -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+<feature name="org.goomba.sm83">
+"#,
+    );
+    for (reg, name) in REGISTERS {
+        let (bits, type_) = match reg {
+            Register::SP => (16, "data_ptr"),
+            Register::PC => (16, "code_ptr"),
+            _ => (8, "uint8"),
+        };
+        let line = format!(r#"<reg name="{name}" bitsize="{bits}" type="{type_}"/>"#);
+        xml.push_str(&line);
+        xml.push('\n');
+    }
+    xml.push_str("</feature>\n</target>\n");
+    xml
+}
+
+fn register_width(reg: Register) -> usize {
+    match reg {
+        Register::SP | Register::PC => 2,
+        _ => 1,
+    }
+}
+
+fn read_registers(emu: &Emulator) -> String {
+    let cpu = emu.cpu();
+    let mut out = String::new();
+    for (reg, _) in REGISTERS {
+        let bytes = cpu.read(reg).to_le_bytes();
+        encode_hex(&mut out, &bytes[..register_width(reg)]);
+    }
+    out
+}
+
+fn write_registers(emu: &mut Emulator, args: &str) -> Result<()> {
+    let mut bytes = decode_hex(args)?.into_iter();
+    let mut cpu = emu.cpu();
+    for (reg, _) in REGISTERS {
+        let mut value = [0; 2];
+        for byte in value.iter_mut().take(register_width(reg)) {
+            *byte = bytes.next().context("too few register bytes")?;
+        }
+        cpu.write(reg, u16::from_le_bytes(value));
+    }
+    emu.set_cpu(&cpu);
+    Ok(())
+}
+
+fn read_register(emu: &Emulator, args: &str) -> Result<String> {
+    let reg = parse_register(args)?;
+    let bytes = emu.cpu().read(reg).to_le_bytes();
+    let mut out = String::new();
+    encode_hex(&mut out, &bytes[..register_width(reg)]);
+    Ok(out)
+}
+
+fn write_register(emu: &mut Emulator, args: &str) -> Result<()> {
+    let (nr, value) = args.split_once('=').context("missing value")?;
+    let reg = parse_register(nr)?;
+    let bytes = decode_hex(value)?;
+
+    let mut value = [0; 2];
+    for (dst, src) in value.iter_mut().zip(bytes) {
+        *dst = src;
+    }
+
+    let mut cpu = emu.cpu();
+    cpu.write(reg, u16::from_le_bytes(value));
+    emu.set_cpu(&cpu);
+    Ok(())
+}
+
+fn parse_register(nr: &str) -> Result<Register> {
+    let nr = usize::from_str_radix(nr, 16)?;
+    match REGISTERS.get(nr) {
+        Some((reg, _)) => Ok(*reg),
+        None => bail!("invalid register: {nr}"),
+    }
+}
+
+fn read_memory(emu: &mut Emulator, args: &str) -> Result<String> {
+    let (addr, len) = parse_addr_len(args)?;
+
+    let mut out = String::new();
+    for i in 0..len {
+        let value = emu.peek(addr.wrapping_add(i));
+        encode_hex(&mut out, &[value]);
+    }
+    Ok(out)
+}
+
+fn write_memory(emu: &mut Emulator, args: &str) -> Result<()> {
+    let (addr, data) = parse_write(args)?;
+    for (i, value) in (0..).zip(data) {
+        emu.poke(addr.wrapping_add(i), value);
+    }
+    Ok(())
+}
+
+fn parse_write(args: &str) -> Result<(u16, Vec<u8>)> {
+    let (range, data) = args.split_once(':').context("missing data")?;
+    let (addr, len) = parse_addr_len(range)?;
+    let data = decode_hex(data)?;
+    if data.len() != usize::from(len) {
+        bail!("length mismatch");
+    }
+    Ok((addr, data))
+}
+
+fn parse_addr_len(args: &str) -> Result<(u16, u16)> {
+    let (addr, len) = args.split_once(',').context("missing length")?;
+    let addr = u16::from_str_radix(addr, 16)?;
+    let len = u16::from_str_radix(len, 16)?;
+    Ok((addr, len))
+}
+
+fn parse_point(args: &str) -> Result<(u8, u16, u16)> {
+    let mut parts = args.split(',');
+    let mut next = || parts.next().context("missing breakpoint field");
+    let kind = next()?.parse()?;
+    let addr = u16::from_str_radix(next()?, 16)?;
+    let len = u16::from_str_radix(next()?, 16)?;
+    Ok((kind, addr, len))
+}
+
+fn remove_point(emu: &mut Emulator, kind: u8, id: u32) {
+    let mut debugger = emu.debugger();
+    match kind {
+        0 | 1 => debugger.remove_breakpoint(id),
+        _ => debugger.remove_watchpoint(id),
+    };
+}
+
+fn encode_hex(out: &mut String, bytes: &[u8]) {
+    for byte in bytes {
+        write!(out, "{byte:02x}").unwrap();
+    }
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        bail!("odd hex length");
+    }
+
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| hex_byte(pair).with_context(|| format!("invalid hex: {s}")))
+        .collect()
+}
+
+/// Parse two hex digits, rejecting anything else `from_str_radix` accepts, like a leading `+`.
+fn hex_byte(pair: &[u8]) -> Option<u8> {
+    if pair.len() != 2 || !pair.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    let pair = std::str::from_utf8(pair).ok()?;
+    u8::from_str_radix(pair, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emulator::Location;
+
+    fn packet(data: &str) -> Vec<u8> {
+        let checksum = compute_checksum(data.as_bytes());
+        format!("${data}#{checksum:02x}").into_bytes()
+    }
+
+    #[test]
+    fn frames_packets() {
+        let mut input = b"+".to_vec();
+        input.extend(packet("g"));
+        input.push(0x03);
+        input.extend(b"$m0,1#");
+
+        assert_eq!(take_frame(&mut input), Some(Frame::Packet(b"g".to_vec())));
+        assert_eq!(take_frame(&mut input), Some(Frame::Interrupt));
+        assert_eq!(take_frame(&mut input), None);
+        assert_eq!(input, b"$m0,1#");
+
+        input.extend(b"fa");
+        assert_eq!(
+            take_frame(&mut input),
+            Some(Frame::Packet(b"m0,1".to_vec()))
+        );
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_checksums() {
+        let mut input = b"$g#00$g#+7".to_vec();
+        assert_eq!(take_frame(&mut input), Some(Frame::Corrupted));
+        assert_eq!(take_frame(&mut input), Some(Frame::Corrupted));
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn checksum_wraps() {
+        assert_eq!(compute_checksum(b""), 0);
+        assert_eq!(compute_checksum(b"OK"), 0x9a);
+        assert_eq!(compute_checksum(&[0xff, 0x02]), 0x01);
+    }
+
+    #[test]
+    fn escapes_round_trip() {
+        let encoded = encode_packet(b"a$#}*b");
+        assert_eq!(encoded, b"$a}\x04}\x03}]}\x0ab#25".to_vec());
+
+        let mut input = encoded;
+        assert_eq!(
+            take_frame(&mut input),
+            Some(Frame::Packet(b"a$#}*b".to_vec()))
+        );
+    }
+
+    #[test]
+    fn decodes_hex() {
+        assert_eq!(decode_hex("00a0FF").unwrap(), [0x00, 0xa0, 0xff]);
+        assert!(decode_hex("0").is_err());
+        assert!(decode_hex("+f").is_err());
+        assert!(decode_hex("-1").is_err());
+        assert!(decode_hex("é").is_err());
+    }
+
+    #[test]
+    fn parses_memory_packets() {
+        assert_eq!(parse_addr_len("c000,10").unwrap(), (0xc000, 0x10));
+        assert!(parse_addr_len("c000").is_err());
+
+        assert_eq!(
+            parse_write("ff80,2:1234").unwrap(),
+            (0xff80, vec![0x12, 0x34])
+        );
+        assert!(parse_write("ff80,3:1234").is_err());
+        assert!(parse_write("ff80,2").is_err());
+    }
+
+    #[test]
+    fn parses_breakpoint_packets() {
+        assert_eq!(parse_point("0,150,1").unwrap(), (0, 0x150, 1));
+        assert_eq!(parse_point("2,c000,4").unwrap(), (2, 0xc000, 4));
+        assert!(parse_point("0,150").is_err());
+    }
+
+    #[test]
+    fn stop_replies() {
+        assert_eq!(stop_reply(&StopReason::Step, false), "T05");
+        assert_eq!(stop_reply(&StopReason::Step, true), "T02");
+        assert_eq!(stop_reply(&StopReason::Breakpoint(1), false), "T05");
+
+        let illegal = StopReason::IllegalOpcode {
+            location: Location {
+                bank: 0,
+                addr: 0x150,
+            },
+            opcode: 0xd3,
+        };
+        assert_eq!(stop_reply(&illegal, false), "T04");
+
+        let watch = |access| StopReason::Watchpoint {
+            id: 1,
+            addr: 0xc000,
+            access,
+        };
+        assert_eq!(stop_reply(&watch(Access::Write), false), "T05watch:c000;");
+        assert_eq!(stop_reply(&watch(Access::Read), false), "T05rwatch:c000;");
+        assert_eq!(
+            stop_reply(&watch(Access::ReadWrite), false),
+            "T05awatch:c000;"
+        );
+    }
+}
@@ -1,31 +1,52 @@
-use anyhow::Result;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
 use log::{error, info};
 use pixels::{Pixels, SurfaceTexture};
 use rfd::FileDialog;
 use winit::event::{Event, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
+use winit::window::{Window, WindowBuilder};
 use winit_input_helper::WinitInputHelper;
 
-use emulator::{Button, Emulator, Frame};
+use emulator::{Button, Emulator, Frame, Profile, StopReason};
+
+use crate::debug_ui::{Command, DebugUi};
+use crate::gdb::{Connection, GdbStub};
+use crate::pacer::{Pacer, FAST_FORWARD_SPEED, NORMAL_SPEED, SPEEDS};
 
 const CODE_CLOSE: i32 = 0;
 const CODE_ERROR: i32 = 1;
 
-pub fn run(emu: Emulator) -> Result<()> {
+/// Time to wait after the last cartridge RAM write before persisting the RAM.
+///
+/// Games usually write their save data in bursts, so we wait for the game to stop writing to avoid
+/// persisting partial saves and hitting the disk on every frame.
+const RAM_SAVE_DELAY: Duration = Duration::from_secs(1);
+
+const TITLE: &str = "Goomba";
+
+pub fn run(
+    emu: Emulator,
+    ram_path: PathBuf,
+    gdb: Option<GdbStub>,
+    profile_path: Option<PathBuf>,
+) -> Result<()> {
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
-        .with_title("Goomba")
-        .build(&event_loop)?;
+    let window = WindowBuilder::new().with_title(TITLE).build(&event_loop)?;
     let size = window.inner_size();
     let surface = SurfaceTexture::new(size.width, size.height, &window);
     let pixels = Pixels::new(Frame::WIDTH, Frame::HEIGHT, surface)?;
+    let debug_ui = DebugUi::new(&event_loop, &window, &pixels);
 
-    let mut handler = Handler::new(emu, pixels);
+    let mut handler = Handler::new(emu, window, pixels, debug_ui, ram_path, gdb, profile_path);
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = match handler.handle(event) {
-            Ok(()) => ControlFlow::Poll,
+            Ok(()) => ControlFlow::WaitUntil(handler.pacer.deadline()),
             Err(code) => ControlFlow::ExitWithCode(code),
         };
     });
@@ -33,30 +54,71 @@ pub fn run(emu: Emulator) -> Result<()> {
 
 struct Handler {
     emulator: Emulator,
+    window: Window,
     pixels: Pixels,
+    debug_ui: DebugUi,
     input: WinitInputHelper,
+    pacer: Pacer,
+    /// Index of the selected speed preset in `SPEEDS`.
+    speed: usize,
+    ram_path: PathBuf,
+    /// Time of the last cartridge RAM write that has not been persisted yet.
+    ram_written: Option<Instant>,
+    gdb: Option<GdbStub>,
+    profile_path: Option<PathBuf>,
+    /// Whether execution was stopped by the debugger UI.
+    paused: bool,
+    /// Description of the last debugger stop, shown in the debugger UI.
+    stop: Option<String>,
 }
 
 impl Handler {
-    fn new(emulator: Emulator, pixels: Pixels) -> Self {
+    fn new(
+        emulator: Emulator,
+        window: Window,
+        pixels: Pixels,
+        debug_ui: DebugUi,
+        ram_path: PathBuf,
+        gdb: Option<GdbStub>,
+        profile_path: Option<PathBuf>,
+    ) -> Self {
         Self {
             emulator,
+            window,
             pixels,
+            debug_ui,
             input: WinitInputHelper::new(),
+            pacer: Pacer::new(),
+            speed: NORMAL_SPEED,
+            ram_path,
+            ram_written: None,
+            gdb,
+            profile_path,
+            paused: false,
+            stop: None,
         }
     }
 
     fn handle(&mut self, event: Event<()>) -> Result<(), i32> {
+        if let Event::WindowEvent { event, .. } = &event {
+            self.debug_ui.handle_event(event);
+        }
+
         if self.input.update(&event) {
             self.handle_close_request()?;
             self.handle_resize()?;
             self.handle_keypresses()?;
-            self.render_frame()?;
+            self.handle_gdb()?;
+            self.run_frames()?;
+            self.autosave_ram();
         }
 
         if event == Event::LoopDestroyed {
             info!("window closed; shutting down");
-            self.save_ram();
+            if self.ram_written.is_some() {
+                self.save_ram();
+            }
+            self.write_profile();
         }
 
         Ok(())
@@ -93,6 +155,15 @@ impl Handler {
             (Button::Select, VirtualKeyCode::Back),
         ];
 
+        if self.input.key_pressed(VirtualKeyCode::F12) {
+            self.debug_ui.toggle();
+            // Hiding the debugger resumes execution, since there is no way to continue otherwise.
+            self.paused &= self.debug_ui.visible();
+        }
+        if self.debug_ui.wants_keyboard() {
+            return Ok(());
+        }
+
         for (button, keycode) in BUTTON_KEYCODES {
             if self.input.key_pressed(keycode) {
                 self.emulator.press_button(button);
@@ -106,36 +177,207 @@ impl Handler {
             self.save_state();
         }
 
+        self.handle_speed_keys();
+
         Ok(())
     }
 
-    fn render_frame(&mut self) -> Result<(), i32> {
-        let frame = self.emulator.render_frame().map_err(|error| {
-            error!("emulator error: {error:#}");
-            CODE_ERROR
-        })?;
+    fn handle_speed_keys(&mut self) {
+        if self.input.key_pressed(VirtualKeyCode::Minus) {
+            self.speed = self.speed.saturating_sub(1);
+        }
+        if self.input.key_pressed(VirtualKeyCode::Equals) {
+            self.speed = (self.speed + 1).min(SPEEDS.len() - 1);
+        }
+
+        let speed = if self.input.key_held(VirtualKeyCode::Tab) {
+            FAST_FORWARD_SPEED
+        } else {
+            SPEEDS[self.speed]
+        };
+        if speed != self.pacer.speed() {
+            self.pacer.set_speed(speed);
+        }
+    }
 
-        frame
-            .write_into(self.pixels.get_frame_mut())
-            .expect("frame buffer has the correct size");
+    fn handle_gdb(&mut self) -> Result<(), i32> {
+        let Some(gdb) = &mut self.gdb else { return Ok(()) };
 
-        self.pixels.render().map_err(|error| {
+        match gdb.poll(&mut self.emulator) {
+            Ok(Connection::Attached) => Ok(()),
+            Ok(Connection::Detached) => {
+                self.gdb = None;
+                Ok(())
+            }
+            Ok(Connection::Killed) => Err(CODE_CLOSE),
+            Err(error) => {
+                error!("GDB connection error: {error:#}");
+                self.gdb = None;
+                Ok(())
+            }
+        }
+    }
+
+    /// Emulate all frames that are due and present the last one.
+    ///
+    /// While the debugger UI is visible, we present on every tick, even if execution is stopped.
+    fn run_frames(&mut self) -> Result<(), i32> {
+        let now = Instant::now();
+        let count = self.pacer.due_frames(now);
+        if count == 0 {
+            return Ok(());
+        }
+
+        let mut frame = None;
+        let mut emulated = 0;
+        if !self.stopped() {
+            for _ in 0..count {
+                match self.emulate_frame() {
+                    Some(f) => frame = Some(f),
+                    None => break,
+                }
+                emulated += 1;
+            }
+        }
+
+        if let Some(frame) = frame {
+            if self.emulator.take_ram_dirty() {
+                self.ram_written = Some(now);
+            }
+
+            frame
+                .write_into(self.pixels.frame_mut())
+                .expect("frame buffer has the correct size");
+            self.pacer.record(emulated);
+        } else if !self.debug_ui.visible() {
+            return Ok(());
+        }
+
+        self.render()?;
+
+        if let Some(perf) = self.pacer.take_stats(now) {
+            let title = format!("{TITLE} - {:.1} FPS ({:.0}%)", perf.fps, perf.speed * 100.);
+            self.window.set_title(&title);
+        }
+
+        Ok(())
+    }
+
+    /// Emulate the next frame, or return `None` if the debugger stopped execution before.
+    fn emulate_frame(&mut self) -> Option<Frame> {
+        loop {
+            let reason = self.emulator.run_until_break();
+            let illegal_opcode = matches!(reason, StopReason::IllegalOpcode { .. });
+
+            match (reason, &mut self.gdb) {
+                (StopReason::Frame(frame), _) => return Some(frame),
+                (reason, Some(gdb)) => {
+                    if let Err(error) = gdb.report_stop(&reason) {
+                        error!("GDB connection error: {error:#}");
+                        self.gdb = None;
+                    }
+                    return None;
+                }
+                (reason, None) if self.debug_ui.visible() || illegal_opcode => {
+                    // Illegal opcodes can't be skipped, so show them in the debugger.
+                    if !self.debug_ui.visible() {
+                        self.debug_ui.toggle();
+                    }
+
+                    let pc = self.emulator.cpu().pc;
+                    let location = match self.emulator.label(pc) {
+                        Some(label) => format!("{pc:04x} ({label})"),
+                        None => format!("{pc:04x}"),
+                    };
+                    let description = match reason {
+                        StopReason::IllegalOpcode { opcode, .. } => {
+                            format!("illegal opcode {opcode:#04x}")
+                        }
+                        reason => format!("{reason:?}"),
+                    };
+                    self.stop = Some(format!("{description} at {location}"));
+                    self.paused = true;
+                    return None;
+                }
+                // Nobody to report the stop to, so keep going.
+                (_, None) => (),
+            }
+        }
+    }
+
+    /// Whether execution is currently stopped by a debugger.
+    fn stopped(&self) -> bool {
+        self.paused || self.gdb.as_ref().is_some_and(|gdb| !gdb.is_running())
+    }
+
+    fn render(&mut self) -> Result<(), i32> {
+        if !self.debug_ui.visible() {
+            return self.pixels.render().map_err(|error| {
+                error!("render error: {error}");
+                CODE_ERROR
+            });
+        }
+
+        let status = match (&self.stop, self.stopped()) {
+            (Some(stop), true) => format!("Stopped: {stop}"),
+            (None, true) => "Stopped".into(),
+            (_, false) => "Running".into(),
+        };
+        let commands = self
+            .debug_ui
+            .prepare(&self.window, &mut self.emulator, &status);
+        for command in commands {
+            self.handle_debug_command(command);
+        }
+
+        let debug_ui = &mut self.debug_ui;
+        let result = self.pixels.render_with(|encoder, target, context| {
+            context.scaling_renderer.render(encoder, target);
+            debug_ui.render(encoder, target, context);
+            Ok(())
+        });
+        result.map_err(|error| {
             error!("render error: {error}");
             CODE_ERROR
         })
     }
 
-    fn save_ram(&self) {
-        let path = FileDialog::new()
-            .set_title("Save cartridge RAM?")
-            .add_filter("RAM dump", &["gb-ram"])
-            .save_file();
+    fn handle_debug_command(&mut self, command: Command) {
+        // Commands only control the local debugger while no GDB client is in charge.
+        if self.gdb.is_some() {
+            return;
+        }
 
-        if let Some(path) = path {
-            self.emulator.save_ram(&path);
+        let mut debugger = self.emulator.debugger();
+        match command {
+            Command::Pause => {
+                debugger.step_instruction();
+                return;
+            }
+            Command::Step => debugger.step_instruction(),
+            Command::StepOver => debugger.step_over(),
+            Command::StepOut => debugger.step_out(),
+            Command::Continue => {
+                debugger.resume();
+                self.stop = None;
+            }
+        }
+        self.paused = false;
+    }
+
+    fn autosave_ram(&mut self) {
+        let Some(written) = self.ram_written else { return };
+
+        if written.elapsed() >= RAM_SAVE_DELAY {
+            self.save_ram();
         }
     }
 
+    fn save_ram(&mut self) {
+        self.emulator.save_ram(&self.ram_path);
+        self.ram_written = None;
+    }
+
     fn save_state(&self) {
         let path = FileDialog::new()
             .set_title("Pick a savestate file")
@@ -146,4 +388,27 @@ impl Handler {
             self.emulator.save_state(&path);
         }
     }
+
+    fn write_profile(&mut self) {
+        let Some(path) = &self.profile_path else { return };
+        let Some(profile) = self.emulator.stop_profile() else { return };
+
+        match write_profile(&profile, path) {
+            Ok(()) => info!("wrote profile to {path:?}"),
+            Err(error) => error!("cannot write profile: {error:#}"),
+        }
+    }
+}
+
+/// Write the profile report to `path` and the folded call stacks next to it.
+fn write_profile(profile: &Profile, path: &Path) -> Result<()> {
+    let folded_path = path.with_extension("folded");
+
+    let file = File::create(path).with_context(|| format!("creating {path:?}"))?;
+    profile.write_report(BufWriter::new(file))?;
+
+    let file = File::create(&folded_path).with_context(|| format!("creating {folded_path:?}"))?;
+    profile.write_folded(BufWriter::new(file))?;
+
+    Ok(())
 }
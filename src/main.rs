@@ -1,11 +1,17 @@
-use std::fs;
+use std::fs::{self, File};
+use std::io::BufWriter;
 use std::path::PathBuf;
 
 use anyhow::{Context, Result};
 
-use emulator::Emulator;
+use emulator::{Emulator, IllegalOpcodePolicy, Symbols, TraceFormat};
 
+use crate::gdb::GdbStub;
+
+mod debug_ui;
+mod gdb;
 mod gui;
+mod pacer;
 
 /// An emulator for the classic GameBoy.
 #[derive(argh::FromArgs)]
@@ -13,9 +19,30 @@ struct Args {
     /// path of the cartridge or savegame file to execute
     #[argh(positional)]
     path: PathBuf,
-    /// path of persistent cartridge RAM
+    /// path of persistent cartridge RAM (default: the game path with a `.sav` extension)
     #[argh(option)]
     ram_path: Option<PathBuf>,
+    /// wait for a GDB connection on the given port before starting
+    #[argh(option)]
+    gdb: Option<u16>,
+    /// write a trace of all executed instructions to the given file
+    #[argh(option)]
+    trace: Option<PathBuf>,
+    /// format of the instruction trace: `doctor` (default) or `annotated`
+    #[argh(option, default = "TraceFormat::Doctor")]
+    trace_format: TraceFormat,
+    /// path of a `.sym` file with symbols for debug output (default: the game path with a `.sym`
+    /// extension, if it exists)
+    #[argh(option)]
+    symbols: Option<PathBuf>,
+    /// profile the game and write a report to the given file, as well as folded call stacks for
+    /// flamegraph tools next to it, with a `.folded` extension
+    #[argh(option)]
+    profile: Option<PathBuf>,
+    /// what to do on illegal opcodes: `lock` up the CPU, like real hardware (default), or `break`
+    /// into the debugger
+    #[argh(option, default = "IllegalOpcodePolicy::default()")]
+    illegal_opcodes: IllegalOpcodePolicy,
 }
 
 fn main() -> Result<()> {
@@ -25,15 +52,39 @@ fn main() -> Result<()> {
 
     let rom_or_save = fs::read(&args.path).with_context(|| format!("opening {:?}", args.path))?;
 
-    let ram = match &args.ram_path {
-        Some(p) if p.exists() => {
-            let ram = fs::read(p).with_context(|| format!("opening {p:?}"))?;
-            Some(ram)
-        }
-        _ => None,
+    let ram_path = args
+        .ram_path
+        .unwrap_or_else(|| args.path.with_extension("sav"));
+    let ram = if ram_path.exists() {
+        let ram = fs::read(&ram_path).with_context(|| format!("opening {ram_path:?}"))?;
+        Some(ram)
+    } else {
+        None
     };
 
-    let emu = Emulator::load(rom_or_save, ram)?;
+    let mut emu = Emulator::load(rom_or_save, ram)?;
+    emu.set_illegal_opcode_policy(args.illegal_opcodes);
+
+    let symbols_path = args.symbols.or_else(|| {
+        let path = args.path.with_extension("sym");
+        path.exists().then_some(path)
+    });
+    if let Some(path) = symbols_path {
+        let text = fs::read_to_string(&path).with_context(|| format!("opening {path:?}"))?;
+        let symbols = Symbols::parse(&text).with_context(|| format!("parsing {path:?}"))?;
+        emu.load_symbols(symbols);
+    }
+
+    if let Some(path) = &args.trace {
+        let file = File::create(path).with_context(|| format!("creating {path:?}"))?;
+        emu.start_trace(Box::new(BufWriter::new(file)), args.trace_format);
+    }
+
+    if args.profile.is_some() {
+        emu.start_profile();
+    }
+
+    let gdb = args.gdb.map(GdbStub::listen).transpose()?;
 
-    gui::run(emu)
+    gui::run(emu, ram_path, gdb, args.profile)
 }
@@ -0,0 +1,199 @@
+use std::time::{Duration, Instant};
+
+use emulator::Emulator;
+
+/// Selectable emulation speeds, relative to the real hardware.
+pub(crate) const SPEEDS: [f64; 6] = [0.25, 0.5, 1., 2., 4., 8.];
+/// Index of the real-time speed in `SPEEDS`.
+pub(crate) const NORMAL_SPEED: usize = 2;
+/// Emulation speed while fast-forwarding.
+pub(crate) const FAST_FORWARD_SPEED: f64 = 8.;
+
+/// Maximum number of frames emulated without presenting them when we fall behind.
+const MAX_FRAME_SKIP: u32 = 4;
+/// Interval at which the performance statistics are updated.
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keeps emulation in step with the real-time frame rate of the GameBoy.
+///
+/// Frame deadlines are derived from a fixed schedule, rather than from the time the previous frame
+/// finished, so timing errors don't accumulate. When we fall behind schedule, frames are emulated
+/// without being presented, up to `MAX_FRAME_SKIP`. If we are even further behind, e.g. because the
+/// process was suspended, we give up on catching up and restart the schedule.
+///
+/// When running faster than real time, frames are still presented at most at the real-time frame
+/// rate and the frames in between are skipped.
+pub(crate) struct Pacer {
+    speed: f64,
+    /// Real-time duration of a frame at the current speed.
+    period: Duration,
+    /// Time at which the next frame is due.
+    deadline: Instant,
+    /// Earliest time at which the next frame may be presented.
+    present_deadline: Instant,
+    stats: Stats,
+}
+
+impl Pacer {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            speed: 1.,
+            period: frame_period(1.),
+            deadline: now,
+            present_deadline: now,
+            stats: Stats::new(now),
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+        self.period = frame_period(speed);
+        self.deadline = Instant::now();
+    }
+
+    /// Return the time at which we need to wake up for the next frame.
+    pub fn deadline(&self) -> Instant {
+        self.deadline.max(self.present_deadline)
+    }
+
+    /// Return the number of frames that are due at `now` and advance the schedule accordingly.
+    ///
+    /// Only the last of the due frames needs to be presented.
+    pub fn due_frames(&mut self, now: Instant) -> u32 {
+        if now < self.deadline() {
+            return 0;
+        }
+
+        if self.speed > 1. {
+            self.present_deadline = now + frame_period(1.);
+        }
+
+        let max_count = ((MAX_FRAME_SKIP + 1) as f64 * self.speed.max(1.)) as u32;
+        let behind = (now - self.deadline).as_nanos() / self.period.as_nanos();
+        let count = behind as u32 + 1;
+        if count > max_count {
+            self.deadline = now + self.period;
+            max_count
+        } else {
+            self.deadline += self.period * count;
+            count
+        }
+    }
+
+    /// Record that `emulated` frames were emulated and one of them was presented.
+    pub fn record(&mut self, emulated: u32) {
+        self.stats.emulated += emulated;
+        self.stats.presented += 1;
+    }
+
+    /// Return new performance statistics, if the last ones are outdated.
+    pub fn take_stats(&mut self, now: Instant) -> Option<Performance> {
+        let elapsed = now - self.stats.start;
+        if elapsed < STATS_INTERVAL {
+            return None;
+        }
+
+        let secs = elapsed.as_secs_f64();
+        let perf = Performance {
+            fps: f64::from(self.stats.presented) / secs,
+            speed: f64::from(self.stats.emulated) / secs / Emulator::FRAME_RATE,
+        };
+        self.stats = Stats::new(now);
+        Some(perf)
+    }
+}
+
+struct Stats {
+    start: Instant,
+    emulated: u32,
+    presented: u32,
+}
+
+impl Stats {
+    fn new(start: Instant) -> Self {
+        Self {
+            start,
+            emulated: 0,
+            presented: 0,
+        }
+    }
+}
+
+pub(crate) struct Performance {
+    /// Number of frames presented per second.
+    pub fps: f64,
+    /// Emulation speed relative to the real hardware.
+    pub speed: f64,
+}
+
+fn frame_period(speed: f64) -> Duration {
+    Duration::from_secs_f64(1. / (Emulator::FRAME_RATE * speed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_follow_schedule() {
+        let mut pacer = Pacer::new();
+        let start = pacer.deadline();
+        let period = frame_period(1.);
+
+        assert_eq!(pacer.due_frames(start), 1);
+        assert_eq!(pacer.due_frames(start), 0);
+        assert_eq!(pacer.due_frames(start + period / 2), 0);
+        assert_eq!(pacer.due_frames(start + period), 1);
+    }
+
+    #[test]
+    fn catches_up_after_stall() {
+        let mut pacer = Pacer::new();
+        let start = pacer.deadline();
+        let period = frame_period(1.);
+
+        assert_eq!(pacer.due_frames(start + period * 3), 4);
+        // The schedule isn't shifted by the stall.
+        assert_eq!(pacer.deadline(), start + period * 4);
+    }
+
+    #[test]
+    fn frame_skip_is_capped() {
+        let mut pacer = Pacer::new();
+        let start = pacer.deadline();
+        let period = frame_period(1.);
+
+        let now = start + period * 100;
+        assert_eq!(pacer.due_frames(now), MAX_FRAME_SKIP + 1);
+        // Instead of catching up, the schedule restarts.
+        assert_eq!(pacer.deadline(), now + period);
+    }
+
+    #[test]
+    fn fast_forward_skips_frames_between_presents() {
+        let mut pacer = Pacer::new();
+        pacer.set_speed(FAST_FORWARD_SPEED);
+        let start = pacer.deadline();
+        let present_period = frame_period(1.);
+
+        assert_eq!(pacer.due_frames(start), 1);
+        // Frames aren't presented faster than in real time.
+        assert_eq!(pacer.deadline(), start + present_period);
+        assert_eq!(pacer.due_frames(start + present_period), 8);
+    }
+
+    #[test]
+    fn frame_skip_cap_scales_with_speed() {
+        let mut pacer = Pacer::new();
+        pacer.set_speed(FAST_FORWARD_SPEED);
+        let start = pacer.deadline();
+
+        let count = pacer.due_frames(start + frame_period(1.) * 100);
+        assert_eq!(count, (MAX_FRAME_SKIP + 1) * FAST_FORWARD_SPEED as u32);
+    }
+}
@@ -2,20 +2,42 @@ use std::collections::VecDeque;
 
 use anyhow::{Context, Result};
 use emulator::{Button, Emulator, Frame};
+use log::error;
 use pixels::wgpu::Color;
 use pixels::{Pixels, PixelsBuilder, SurfaceTexture};
 use winit::dpi::LogicalSize;
 use winit::event::Event as WinitEvent;
 use winit::window::Window;
 
+use crate::storage::{Storage, Store};
+use crate::web;
+
+/// Number of frames to wait after the last cartridge RAM write before persisting the RAM.
+const RAM_SAVE_DELAY: u32 = 60;
+/// Number of frames between two persisted snapshots of the running game.
+const SNAPSHOT_INTERVAL: u32 = 600;
+
+/// Selectable emulation speeds, relative to the display's frame rate.
+const SPEEDS: [f64; 6] = [0.25, 0.5, 1., 2., 4., 8.];
+/// Index of the normal speed in `SPEEDS`.
+const NORMAL_SPEED: usize = 2;
+/// Emulation speed while fast-forwarding.
+const FAST_FORWARD_SPEED: f64 = 8.;
+
 pub struct App {
     window: Window,
     pixels: Pixels,
+    storage: Option<Storage>,
     game: Option<Game>,
+    /// Index of the selected speed preset in `SPEEDS`.
+    speed: usize,
+    fast_forward: bool,
+    /// Fractional number of frames that are due to be emulated.
+    frame_credit: f64,
 }
 
 impl App {
-    pub async fn create(window: Window) -> Self {
+    pub async fn create(window: Window, storage: Option<Storage>) -> Self {
         let size = window.inner_size();
         let surface = SurfaceTexture::new(size.width, size.height, &window);
         let pixels = PixelsBuilder::new(Frame::WIDTH, Frame::HEIGHT, surface)
@@ -27,19 +49,34 @@ impl App {
         Self {
             window,
             pixels,
+            storage,
             game: None,
+            speed: NORMAL_SPEED,
+            fast_forward: false,
+            frame_credit: 0.,
         }
     }
 
-    pub fn take_ram_save(&mut self) -> Option<RamSave> {
+    pub fn speed(&self) -> f64 {
+        if self.fast_forward {
+            FAST_FORWARD_SPEED
+        } else {
+            SPEEDS[self.speed]
+        }
+    }
+
+    pub fn emulator(&self) -> Option<&Emulator> {
+        self.game.as_ref().map(|game| &game.emulator)
+    }
+
+    pub fn take_file_save(&mut self) -> Option<FileSave> {
         let Some(game) = &mut self.game else { return None };
 
-        game.ram_save.take()
+        game.file_save.take()
     }
 
     pub fn handle_winit_event(&mut self, event: WinitEvent<()>) -> Result<()> {
         if event == WinitEvent::MainEventsCleared {
-            self.handle_button_event();
             self.render_frame()?;
         }
 
@@ -50,8 +87,23 @@ impl App {
         use GuiEvent::*;
         match event {
             Resize(size) => self.resize(size)?,
-            LoadGame { name, rom, ram } => self.load_game(name, rom, ram)?,
+            LoadGame { name, rom, ram } => self.store_game(name, rom, ram)?,
+            ResumeGame {
+                name,
+                rom_or_save,
+                ram,
+            } => {
+                self.load_game(name, rom_or_save, ram)?;
+            }
             SaveRam => self.save_ram()?,
+            SaveState => self.save_state()?,
+            LoadState { name, state } => {
+                self.load_game(name, state, None)?;
+            }
+            FastForward(on) => self.fast_forward = on,
+            SpeedUp => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+            SlowDown => self.speed = self.speed.saturating_sub(1),
+            CycleSpeed => self.speed = (self.speed + 1) % SPEEDS.len(),
             Button(evt) => self.register_button_event(evt),
         }
 
@@ -79,24 +131,141 @@ impl App {
             .context("resize error")
     }
 
-    fn load_game(&mut self, name: String, rom: Vec<u8>, ram: Option<Vec<u8>>) -> Result<()> {
-        let emulator = Emulator::load(rom, ram)?;
+    /// Load a new game and add it to the persistent game library.
+    ///
+    /// Games that fail to load aren't added, so the library only contains playable games.
+    fn store_game(&mut self, name: String, rom: Vec<u8>, ram: Option<Vec<u8>>) -> Result<()> {
+        if !self.load_game(name.clone(), rom.clone(), ram.clone())? {
+            return Ok(());
+        }
+
+        self.persist(Store::Rom, name.clone(), rom);
+        if let Some(ram) = ram {
+            self.persist(Store::Ram, name, ram);
+        }
+        self.persist_snapshot()
+    }
+
+    /// Load a game, returning whether it was loaded successfully.
+    ///
+    /// Errors in the provided files are reported to the user, rather than returned.
+    fn load_game(&mut self, name: String, rom: Vec<u8>, ram: Option<Vec<u8>>) -> Result<bool> {
+        self.suspend_game()?;
+
+        let emulator = match Emulator::load(rom, ram) {
+            Ok(emulator) => emulator,
+            Err(error) => {
+                let message = format!("Error loading {name}: {error}");
+                web::window().alert_with_message(&message).unwrap();
+                return Ok(false);
+            }
+        };
         let game = Game {
             name,
             emulator,
-            ram_save: None,
+            file_save: None,
+            ram_written: None,
+            frames_since_snapshot: 0,
             button_events: Default::default(),
         };
         self.game = Some(game);
+        Ok(true)
+    }
+
+    /// Persist all state of the current game, so it can be resumed later.
+    fn suspend_game(&mut self) -> Result<()> {
+        let Some(game) = &self.game else { return Ok(()) };
+
+        if game.ram_written.is_some() {
+            self.persist_ram()?;
+        }
+        self.persist_snapshot()
+    }
+
+    fn autosave(&mut self) -> Result<()> {
+        let Some(game) = &mut self.game else { return Ok(()) };
+
+        if game.emulator.take_ram_dirty() {
+            game.ram_written = Some(0);
+        }
+        let save_ram = match &mut game.ram_written {
+            Some(frames) if *frames >= RAM_SAVE_DELAY => true,
+            Some(frames) => {
+                *frames += 1;
+                false
+            }
+            None => false,
+        };
+
+        game.frames_since_snapshot += 1;
+        let save_snapshot = game.frames_since_snapshot >= SNAPSHOT_INTERVAL;
+
+        // The snapshot includes the RAM, so we also update it when the RAM changes, to ensure
+        // resuming the game doesn't lose in-game saves.
+        if save_ram {
+            self.persist_ram()?;
+        }
+        if save_ram || save_snapshot {
+            self.persist_snapshot()?;
+        }
         Ok(())
     }
 
-    fn save_ram(&mut self) -> Result<()> {
+    fn persist_ram(&mut self) -> Result<()> {
         let Some(game) = &mut self.game else { return Ok(()) };
 
         let ram = game.emulator.dump_ram()?;
+        game.ram_written = None;
+
+        let name = game.name.clone();
+        self.persist(Store::Ram, name, ram);
+        Ok(())
+    }
+
+    fn persist_snapshot(&mut self) -> Result<()> {
+        let Some(game) = &mut self.game else { return Ok(()) };
+
+        let state = game.emulator.dump_state()?;
+        game.frames_since_snapshot = 0;
+
+        let name = game.name.clone();
+        self.persist(Store::State, name, state);
+        Ok(())
+    }
+
+    fn persist(&self, store: Store, name: String, data: Vec<u8>) {
+        let Some(storage) = self.storage.clone() else { return };
+
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(error) = storage.put(store, &name, &data).await {
+                error!("error persisting {store:?} of {name}: {error:#}");
+            }
+        });
+    }
+
+    fn save_ram(&mut self) -> Result<()> {
+        let Some(game) = &mut self.game else { return Ok(()) };
+
+        let data = game.emulator.dump_ram()?;
         let name = format!("{}.gb-ram", game.name);
-        game.ram_save = Some(RamSave { name, ram });
+        game.file_save = Some(FileSave { name, data });
+        Ok(())
+    }
+
+    fn save_state(&mut self) -> Result<()> {
+        let Some(game) = &mut self.game else { return Ok(()) };
+
+        let data = game.emulator.dump_state()?;
+        let name = format!("{}.gb-save", game.name);
+        game.file_save = Some(FileSave {
+            name,
+            data: data.clone(),
+        });
+
+        // Also make the savestate the one the game is resumed from.
+        game.frames_since_snapshot = 0;
+        let name = game.name.clone();
+        self.persist(Store::State, name, data);
         Ok(())
     }
 
@@ -106,14 +275,30 @@ impl App {
         game.button_events.push_back(event);
     }
 
+    /// Emulate all frames that are due at the current speed and present the last one.
+    ///
+    /// This is called once per frame of the display, so the speed is relative to the display's
+    /// frame rate, rather than the real hardware's.
     fn render_frame(&mut self) -> Result<()> {
-        let frame = match &mut self.game {
-            Some(game) => game.emulator.render_frame()?,
-            None => Frame::default(),
-        };
+        self.frame_credit += self.speed();
+        let count = self.frame_credit as u32;
+        self.frame_credit -= f64::from(count);
+
+        if count == 0 {
+            return Ok(());
+        }
+
+        let mut frame = Frame::default();
+        for _ in 0..count {
+            self.handle_button_event();
+
+            let Some(game) = &mut self.game else { break };
+            frame = game.emulator.render_frame()?;
+            self.autosave()?;
+        }
 
         frame
-            .write_into(self.pixels.get_frame_mut())
+            .write_into(self.pixels.frame_mut())
             .expect("frame buffer has the correct size");
 
         self.pixels.render().context("render error")
@@ -123,8 +308,12 @@ impl App {
 struct Game {
     name: String,
     emulator: Emulator,
-    /// Holds the `RamSave` from the last save request.
-    ram_save: Option<RamSave>,
+    /// Holds the `FileSave` from the last RAM or state save request.
+    file_save: Option<FileSave>,
+    /// Number of frames since the last cartridge RAM write that has not been persisted yet.
+    ram_written: Option<u32>,
+    /// Number of frames since the game state was last persisted.
+    frames_since_snapshot: u32,
     /// A queue containing received button events.
     ///
     /// We don't handle button events directly but defer them to ensure only a single button event
@@ -133,20 +322,37 @@ struct Game {
     button_events: VecDeque<ButtonEvent>,
 }
 
-pub struct RamSave {
+pub struct FileSave {
     pub name: String,
-    pub ram: Vec<u8>,
+    pub data: Vec<u8>,
 }
 
 #[derive(Debug)]
 pub enum GuiEvent {
     Resize(LogicalSize<u32>),
+    /// Load a game from user-provided files and add it to the game library.
     LoadGame {
         name: String,
         rom: Vec<u8>,
         ram: Option<Vec<u8>>,
     },
+    /// Load a game from the game library.
+    ResumeGame {
+        name: String,
+        rom_or_save: Vec<u8>,
+        ram: Option<Vec<u8>>,
+    },
     SaveRam,
+    SaveState,
+    FastForward(bool),
+    SpeedUp,
+    SlowDown,
+    CycleSpeed,
+    /// Load a game from a user-provided savestate file.
+    LoadState {
+        name: String,
+        state: Vec<u8>,
+    },
     Button(ButtonEvent),
 }
 
@@ -166,11 +372,19 @@ impl GuiEvent {
     }
 
     pub fn for_key_press(key: &str) -> Option<Self> {
-        key_to_button(key).map(Self::button_pressed)
+        match key {
+            "Tab" => Some(Self::FastForward(true)),
+            "-" => Some(Self::SlowDown),
+            "=" => Some(Self::SpeedUp),
+            _ => key_to_button(key).map(Self::button_pressed),
+        }
     }
 
     pub fn for_key_release(key: &str) -> Option<Self> {
-        key_to_button(key).map(Self::button_released)
+        match key {
+            "Tab" => Some(Self::FastForward(false)),
+            _ => key_to_button(key).map(Self::button_released),
+        }
     }
 }
 
@@ -1,21 +1,23 @@
 use std::sync::mpsc::{self, Sender, TryRecvError};
 
-use anyhow::bail;
-use emulator::Button;
+use anyhow::{bail, Context, Result};
+use emulator::{Button, Emulator, Image, TileMap};
 use futures::future::OptionFuture;
 use js_sys::{Array, Uint8Array};
 use log::error;
-use wasm_bindgen::JsCast;
+use wasm_bindgen::{Clamped, JsCast};
 use web_sys::{
-    Blob, Element, Event, File, HtmlAnchorElement, HtmlInputElement, KeyboardEvent, PointerEvent,
-    Url,
+    Blob, CanvasRenderingContext2d, Element, Event, File, HtmlAnchorElement, HtmlCanvasElement,
+    HtmlInputElement, ImageData, KeyboardEvent, PointerEvent, Url,
 };
 use winit::dpi::LogicalSize;
+use winit::event::Event as WinitEvent;
 use winit::event_loop::EventLoop;
 use winit::platform::web::{WindowBuilderExtWebSys, WindowExtWebSys};
 use winit::window::{Window, WindowBuilder};
 
 use crate::app::{App, GuiEvent};
+use crate::storage::{Storage, Store};
 use crate::web;
 
 pub async fn run() {
@@ -29,23 +31,43 @@ pub async fn run() {
 
     attach_canvas(&window);
 
-    let mut app = App::create(window).await;
+    let storage = match Storage::open().await {
+        Ok(storage) => Some(storage),
+        Err(error) => {
+            error!("error opening storage: {error:#}");
+            None
+        }
+    };
+
+    let mut app = App::create(window, storage.clone()).await;
 
     let (event_tx, event_rx) = mpsc::channel();
-    register_event_listeners(event_tx);
+    register_event_listeners(event_tx, storage);
 
-    let mut try_handle = move |event: winit::event::Event<()>| {
+    let mut try_handle = move |event: WinitEvent<()>| {
         match event_rx.try_recv() {
-            Ok(event) => app.handle_gui_event(event)?,
+            Ok(event) => {
+                app.handle_gui_event(event)?;
+                show_speed(app.speed());
+            }
             Err(TryRecvError::Empty) => (),
             Err(TryRecvError::Disconnected) => bail!("event_tx disconnected"),
         }
 
-        if let Some(save) = app.take_ram_save() {
-            save_file(&save.ram, &save.name);
+        if let Some(save) = app.take_file_save() {
+            save_file(&save.data, &save.name);
+        }
+
+        let frame_done = event == WinitEvent::MainEventsCleared;
+        app.handle_winit_event(event)?;
+
+        if frame_done && !get_vram().has_attribute("hidden") {
+            if let Some(emulator) = app.emulator() {
+                show_vram(emulator);
+            }
         }
 
-        app.handle_winit_event(event)
+        Ok(())
     };
 
     event_loop.run(move |event, _, control_flow| {
@@ -65,7 +87,7 @@ fn attach_canvas(window: &Window) {
         .expect("error appending canvas");
 }
 
-fn register_event_listeners(event_tx: Sender<GuiEvent>) {
+fn register_event_listeners(event_tx: Sender<GuiEvent>, storage: Option<Storage>) {
     // resizing
     web::add_event_listener(&web::window(), "resize", {
         let tx = event_tx.clone();
@@ -84,13 +106,31 @@ fn register_event_listeners(event_tx: Sender<GuiEvent>) {
 
     // menu buttons
     web::add_event_listener(&get_load_input(), "change", {
+        let storage = storage.clone();
         let tx = event_tx.clone();
-        move |e| on_load_input_change(e, tx.clone())
+        move |e| on_load_input_change(e, storage.clone(), tx.clone())
     });
     web::add_event_listener(&get_save_button(), "click", {
         let tx = event_tx.clone();
         move |e| on_save_button_click(e, tx.clone())
     });
+    web::add_event_listener(&get_save_state_button(), "click", {
+        let tx = event_tx.clone();
+        move |e| on_save_state_button_click(e, tx.clone())
+    });
+    web::add_event_listener(&get_load_state_input(), "change", {
+        let tx = event_tx.clone();
+        move |e| on_load_state_input_change(e, tx.clone())
+    });
+    web::add_event_listener(&get_speed_button(), "click", {
+        let tx = event_tx.clone();
+        move |e| on_speed_button_click(e, tx.clone())
+    });
+    web::add_event_listener(&get_library_button(), "click", {
+        let tx = event_tx.clone();
+        move |e| on_library_button_click(e, storage.clone(), tx.clone())
+    });
+    web::add_event_listener(&get_vram_button(), "click", on_vram_button_click);
 
     // joypad buttons
     for (btn, elem) in get_joypad_buttons() {
@@ -119,6 +159,11 @@ fn on_window_resize(_e: Event, tx: Sender<GuiEvent>) {
 }
 
 fn on_key_press(e: KeyboardEvent, tx: Sender<GuiEvent>) {
+    // Keep the tab key from moving the focus, as we use it for fast-forwarding.
+    if e.key() == "Tab" {
+        e.prevent_default();
+    }
+
     if let Some(event) = GuiEvent::for_key_press(&e.key()) {
         tx.send(event).unwrap();
     }
@@ -150,7 +195,7 @@ fn on_button_enter(e: PointerEvent, btn: Button, tx: Sender<GuiEvent>) {
     }
 }
 
-fn on_load_input_change(_e: Event, tx: Sender<GuiEvent>) {
+fn on_load_input_change(_e: Event, storage: Option<Storage>, tx: Sender<GuiEvent>) {
     let input = get_load_input();
     let files = input.files().unwrap();
 
@@ -168,13 +213,13 @@ fn on_load_input_change(_e: Event, tx: Sender<GuiEvent>) {
     }
 
     if let Some(rom) = rom {
-        load_rom_and_ram(rom, ram, tx);
+        load_rom_and_ram(rom, ram, storage, tx);
     } else {
         web::window().alert_with_message("No ROM provided").unwrap();
     }
 }
 
-fn load_rom_and_ram(rom: File, ram: Option<File>, tx: Sender<GuiEvent>) {
+fn load_rom_and_ram(rom: File, ram: Option<File>, storage: Option<Storage>, tx: Sender<GuiEvent>) {
     let filename = rom.name();
     let name = match filename.strip_suffix(".gb") {
         Some(name) => name.into(),
@@ -186,6 +231,19 @@ fn load_rom_and_ram(rom: File, ram: Option<File>, tx: Sender<GuiEvent>) {
 
     wasm_bindgen_futures::spawn_local(async move {
         let (rom, ram) = futures::join!(read_rom, read_ram);
+
+        // If no RAM file was provided, continue with the RAM persisted in the game library.
+        let ram = match (ram, storage) {
+            (None, Some(storage)) => storage
+                .get(Store::Ram, &name)
+                .await
+                .unwrap_or_else(|error| {
+                    error!("error reading persisted RAM: {error:#}");
+                    None
+                }),
+            (ram, _) => ram,
+        };
+
         let event = GuiEvent::LoadGame { name, rom, ram };
         tx.send(event).unwrap();
     });
@@ -218,6 +276,196 @@ fn on_save_button_click(_e: Event, tx: Sender<GuiEvent>) {
     tx.send(event).unwrap();
 }
 
+fn on_save_state_button_click(_e: Event, tx: Sender<GuiEvent>) {
+    let event = GuiEvent::SaveState;
+    tx.send(event).unwrap();
+}
+
+fn on_load_state_input_change(_e: Event, tx: Sender<GuiEvent>) {
+    let input = get_load_state_input();
+    let Some(file) = input.files().and_then(|files| files.item(0)) else { return };
+    // Reset the input, so selecting the same file again triggers another change event.
+    input.set_value("");
+
+    let filename = file.name();
+    let name = match filename.strip_suffix(".gb-save") {
+        Some(name) => name.into(),
+        None => filename,
+    };
+
+    wasm_bindgen_futures::spawn_local(async move {
+        let state = read_file(file).await;
+        let event = GuiEvent::LoadState { name, state };
+        tx.send(event).unwrap();
+    });
+}
+
+fn on_speed_button_click(_e: Event, tx: Sender<GuiEvent>) {
+    let event = GuiEvent::CycleSpeed;
+    tx.send(event).unwrap();
+}
+
+fn show_speed(speed: f64) {
+    let label = get_speed_label();
+    label.set_text_content(Some(&format!("{speed}x")));
+}
+
+fn on_library_button_click(_e: Event, storage: Option<Storage>, tx: Sender<GuiEvent>) {
+    let library = get_library();
+    let hidden = library.toggle_attribute("hidden").unwrap();
+    if hidden {
+        return;
+    }
+
+    let Some(storage) = storage else {
+        library.set_text_content(Some("Game library not available"));
+        return;
+    };
+
+    wasm_bindgen_futures::spawn_local(async move {
+        match storage.games().await {
+            Ok(games) => show_library(games, storage, tx),
+            Err(error) => error!("error reading game library: {error:#}"),
+        }
+    });
+}
+
+fn show_library(games: Vec<String>, storage: Storage, tx: Sender<GuiEvent>) {
+    let library = get_library();
+    library.set_text_content(None);
+
+    if games.is_empty() {
+        library.set_text_content(Some("No games loaded yet"));
+        return;
+    }
+
+    for name in games {
+        let entry = web::document().create_element("div").unwrap();
+        entry.set_class_name("button library-entry");
+        entry.set_text_content(Some(&name));
+
+        web::add_event_listener(&entry, "click", {
+            let storage = storage.clone();
+            let tx = tx.clone();
+            move |e| on_library_entry_click(e, name.clone(), storage.clone(), tx.clone())
+        });
+
+        library.append_child(&entry).unwrap();
+    }
+}
+
+fn on_library_entry_click(_e: Event, name: String, storage: Storage, tx: Sender<GuiEvent>) {
+    get_library().set_attribute("hidden", "").unwrap();
+
+    wasm_bindgen_futures::spawn_local(async move {
+        match read_library_game(name, &storage).await {
+            Ok(event) => tx.send(event).unwrap(),
+            Err(error) => error!("error reading game from library: {error:#}"),
+        }
+    });
+}
+
+async fn read_library_game(name: String, storage: &Storage) -> Result<GuiEvent> {
+    // Prefer resuming from the last snapshot, if there is one.
+    if let Some(save) = storage.get(Store::State, &name).await? {
+        return Ok(GuiEvent::ResumeGame {
+            name,
+            rom_or_save: save,
+            ram: None,
+        });
+    }
+
+    let rom = storage
+        .get(Store::Rom, &name)
+        .await?
+        .context("ROM missing from library")?;
+    let ram = storage.get(Store::Ram, &name).await?;
+
+    Ok(GuiEvent::ResumeGame {
+        name,
+        rom_or_save: rom,
+        ram,
+    })
+}
+
+fn on_vram_button_click(_e: Event) {
+    get_vram().toggle_attribute("hidden").unwrap();
+}
+
+/// Number of object images per row in the OAM canvas.
+const OAM_COLUMNS: u32 = 8;
+/// Size of an object image cell in the OAM canvas, including spacing.
+const OAM_CELL: (u32, u32) = (10, 18);
+
+fn show_vram(emulator: &Emulator) {
+    draw_image(&get_canvas("vram-tiles"), &emulator.render_tiles(), 0, 0);
+    draw_image(
+        &get_canvas("vram-map-low"),
+        &emulator.render_tile_map(TileMap::Low),
+        0,
+        0,
+    );
+    draw_image(
+        &get_canvas("vram-map-high"),
+        &emulator.render_tile_map(TileMap::High),
+        0,
+        0,
+    );
+
+    let canvas = get_canvas("vram-oam");
+    canvas_context(&canvas).clear_rect(
+        0.,
+        0.,
+        f64::from(canvas.width()),
+        f64::from(canvas.height()),
+    );
+
+    let mut info = String::new();
+    for (i, obj) in emulator.objects().iter().enumerate() {
+        let i = i as u32;
+        let x = (i % OAM_COLUMNS) * OAM_CELL.0;
+        let y = (i / OAM_COLUMNS) * OAM_CELL.1;
+        draw_image(&canvas, &obj.image, x, y);
+
+        let a = &obj.attributes;
+        info += &format!(
+            "{i:02} x={:3} y={:3} tile={:02x} obp{} {}{}{}\n",
+            a.x,
+            a.y,
+            a.tile_id,
+            u8::from(a.flags.palette()),
+            if a.flags.x_flip() { "X" } else { "-" },
+            if a.flags.y_flip() { "Y" } else { "-" },
+            if a.flags.bg_over_obj() { " bg" } else { "" },
+        );
+    }
+    get_vram_oam_info().set_text_content(Some(&info));
+}
+
+fn draw_image(canvas: &HtmlCanvasElement, image: &Image, x: u32, y: u32) {
+    let data = ImageData::new_with_u8_clamped_array_and_sh(
+        Clamped(image.rgba()),
+        image.width(),
+        image.height(),
+    )
+    .unwrap();
+    canvas_context(canvas)
+        .put_image_data(&data, f64::from(x), f64::from(y))
+        .unwrap();
+}
+
+fn canvas_context(canvas: &HtmlCanvasElement) -> CanvasRenderingContext2d {
+    canvas
+        .get_context("2d")
+        .unwrap()
+        .expect("canvas has a 2d context")
+        .unchecked_into()
+}
+
+fn get_canvas(id: &str) -> HtmlCanvasElement {
+    web::get_element_by_id(id)
+}
+
 fn get_lcd() -> Element {
     web::get_element_by_id("lcd")
 }
@@ -230,6 +478,42 @@ fn get_save_button() -> Element {
     web::get_element_by_id("button-save")
 }
 
+fn get_save_state_button() -> Element {
+    web::get_element_by_id("button-save-state")
+}
+
+fn get_load_state_input() -> HtmlInputElement {
+    web::get_element_by_id("input-load-state")
+}
+
+fn get_speed_button() -> Element {
+    web::get_element_by_id("button-speed")
+}
+
+fn get_speed_label() -> Element {
+    web::get_element_by_id("speed-label")
+}
+
+fn get_library_button() -> Element {
+    web::get_element_by_id("button-library")
+}
+
+fn get_library() -> Element {
+    web::get_element_by_id("library")
+}
+
+fn get_vram_button() -> Element {
+    web::get_element_by_id("button-vram")
+}
+
+fn get_vram() -> Element {
+    web::get_element_by_id("vram")
+}
+
+fn get_vram_oam_info() -> Element {
+    web::get_element_by_id("vram-oam-info")
+}
+
 fn get_joypad_buttons() -> Vec<(Button, Element)> {
     let button_ids = [
         (Button::Up, "button-up"),
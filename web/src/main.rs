@@ -1,5 +1,6 @@
 mod app;
 mod gui;
+mod storage;
 mod web;
 
 fn main() {
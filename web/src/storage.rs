@@ -0,0 +1,142 @@
+//! Persistent game storage backed by the browser's IndexedDB.
+//!
+//! Every game is identified by its name. For each game we store the ROM, the last autosaved
+//! cartridge RAM and the last savestate, each in their own object store so they can be updated
+//! independently.
+
+use anyhow::{anyhow, Context, Result};
+use js_sys::{Array, Promise, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbObjectStore, IdbRequest, IdbTransactionMode};
+
+use crate::web;
+
+const DB_NAME: &str = "goomba";
+const DB_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Store {
+    Rom,
+    Ram,
+    State,
+}
+
+impl Store {
+    const ALL: [Self; 3] = [Self::Rom, Self::Ram, Self::State];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Rom => "roms",
+            Self::Ram => "rams",
+            Self::State => "states",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Storage {
+    db: IdbDatabase,
+}
+
+impl Storage {
+    pub async fn open() -> Result<Self> {
+        let factory = web::window()
+            .indexed_db()
+            .map_err(js_error)?
+            .context("IndexedDB not supported")?;
+        let request = factory
+            .open_with_u32(DB_NAME, DB_VERSION)
+            .map_err(js_error)?;
+
+        request.set_onupgradeneeded(Some(&web::js_function({
+            let request = request.clone();
+            move |_| {
+                let db: IdbDatabase = request.result().unwrap().unchecked_into();
+                let existing = db.object_store_names();
+                for store in Store::ALL {
+                    if !existing.contains(store.name()) {
+                        db.create_object_store(store.name()).unwrap();
+                    }
+                }
+            }
+        })));
+
+        let db = wait(&request).await.context("opening database")?;
+        Ok(Self {
+            db: db.unchecked_into(),
+        })
+    }
+
+    pub async fn get(&self, store: Store, name: &str) -> Result<Option<Vec<u8>>> {
+        let request = self
+            .object_store(store, IdbTransactionMode::Readonly)?
+            .get(&JsValue::from_str(name))
+            .map_err(js_error)?;
+
+        let value = wait(&request).await?;
+        if value.is_undefined() {
+            Ok(None)
+        } else {
+            Ok(Some(Uint8Array::new(&value).to_vec()))
+        }
+    }
+
+    pub async fn put(&self, store: Store, name: &str, data: &[u8]) -> Result<()> {
+        let value = Uint8Array::from(data);
+        let request = self
+            .object_store(store, IdbTransactionMode::Readwrite)?
+            .put_with_key(&value, &JsValue::from_str(name))
+            .map_err(js_error)?;
+
+        wait(&request).await?;
+        Ok(())
+    }
+
+    /// Return the names of all stored games.
+    pub async fn games(&self) -> Result<Vec<String>> {
+        let request = self
+            .object_store(Store::Rom, IdbTransactionMode::Readonly)?
+            .get_all_keys()
+            .map_err(js_error)?;
+
+        let keys: Array = wait(&request).await?.unchecked_into();
+        let names = keys.iter().filter_map(|key| key.as_string()).collect();
+        Ok(names)
+    }
+
+    fn object_store(&self, store: Store, mode: IdbTransactionMode) -> Result<IdbObjectStore> {
+        let name = store.name();
+        let transaction = self
+            .db
+            .transaction_with_str_and_mode(name, mode)
+            .map_err(js_error)?;
+        transaction.object_store(name).map_err(js_error)
+    }
+}
+
+/// Wait for the given request to complete and return its result.
+async fn wait(request: &IdbRequest) -> Result<JsValue> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&web::js_function({
+            let request = request.clone();
+            move |_| {
+                let result = request.result().unwrap();
+                resolve.call1(&JsValue::NULL, &result).unwrap();
+            }
+        })));
+        request.set_onerror(Some(&web::js_function({
+            let request = request.clone();
+            move |_| {
+                let error = request.error().ok().flatten();
+                reject.call1(&JsValue::NULL, &error.into()).unwrap();
+            }
+        })));
+    });
+
+    JsFuture::from(promise).await.map_err(js_error)
+}
+
+fn js_error(value: JsValue) -> anyhow::Error {
+    anyhow!("{value:?}")
+}